@@ -1,4 +1,4 @@
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct AllowList {
     domains: Vec<String>,
 }
@@ -8,6 +8,10 @@ impl AllowList {
         Self { domains }
     }
 
+    pub fn domains(&self) -> &[String] {
+        &self.domains
+    }
+
     pub fn in_scope(&self, url: &str) -> bool {
         if self.domains.is_empty() {
             return true;
@@ -0,0 +1,158 @@
+use std::{
+    fmt,
+    io::Read,
+    sync::{Arc, OnceLock},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use bytes::Bytes;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::analyzer::stages::js::analyzer::{JsAst, parse_module};
+
+// a small compressed body must not inflate to whatever it likes; past this it is not decoded at all
+pub const MAX_DECODED_SIZE: usize = 256 * 1024 * 1024;
+
+#[derive(Default)]
+struct Cache {
+    decoded: OnceLock<Option<Bytes>>,
+    text: OnceLock<Option<Arc<str>>>,
+    ast: OnceLock<Result<Arc<JsAst>, String>>,
+}
+
+// clones share the bytes and whatever was already decoded or parsed from them
+#[derive(Clone, Default)]
+pub struct Body {
+    raw: Bytes,
+    cache: Arc<Cache>,
+}
+
+impl Body {
+    pub fn new(raw: Bytes) -> Self {
+        Self {
+            raw,
+            cache: Arc::default(),
+        }
+    }
+
+    pub fn raw(&self) -> &Bytes {
+        &self.raw
+    }
+
+    pub fn len(&self) -> usize {
+        self.raw.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+
+    // the encoding lives on the response; a body is only ever decoded with the one it arrived with
+    pub fn decoded(&self, encoding: &str) -> Option<&Bytes> {
+        self.cache
+            .decoded
+            .get_or_init(|| decode(&self.raw, encoding, MAX_DECODED_SIZE))
+            .as_ref()
+    }
+
+    pub fn text(&self, encoding: &str) -> Option<&str> {
+        self.cache
+            .text
+            .get_or_init(|| {
+                let decoded = self.decoded(encoding)?;
+                std::str::from_utf8(decoded).ok().map(Arc::from)
+            })
+            .as_deref()
+    }
+
+    pub fn ast(&self, encoding: &str, filename: &str) -> anyhow::Result<Arc<JsAst>> {
+        self.cache
+            .ast
+            .get_or_init(|| {
+                let text = self
+                    .text(encoding)
+                    .ok_or_else(|| "corpo não é texto UTF-8".to_string())?;
+                parse_module(filename, text)
+                    .map(Arc::new)
+                    .map_err(|e| e.to_string())
+            })
+            .clone()
+            .map_err(|e| anyhow::anyhow!(e))
+    }
+}
+
+fn decode(raw: &Bytes, encoding: &str, limit: usize) -> Option<Bytes> {
+    match encoding {
+        "gzip" => read_capped(flate2::read::GzDecoder::new(raw.as_ref()), limit),
+        "br" => read_capped(brotli::Decompressor::new(raw.as_ref(), 4096), limit),
+        "identity" | "" => Some(raw.clone()),
+        other => {
+            println!("⚠️ Encoding não suportado: {}", other);
+            None
+        }
+    }
+}
+
+fn read_capped(decoder: impl Read, limit: usize) -> Option<Bytes> {
+    let mut buf = Vec::new();
+    decoder.take(limit as u64 + 1).read_to_end(&mut buf).ok();
+    if buf.len() > limit {
+        println!("⚠️ Corpo descomprimido passa de {} bytes, ignorado.", limit);
+        return None;
+    }
+    Some(buf.into())
+}
+
+impl From<Bytes> for Body {
+    fn from(raw: Bytes) -> Self {
+        Self::new(raw)
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(raw: Vec<u8>) -> Self {
+        Self::new(raw.into())
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Body({} bytes)", self.raw.len())
+    }
+}
+
+impl Serialize for Body {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(&self.raw))
+    }
+}
+
+impl<'de> Deserialize<'de> for Body {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let raw = STANDARD.decode(encoded).map_err(serde::de::Error::custom)?;
+        Ok(raw.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn gzip(data: &[u8]) -> Bytes {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap().into()
+    }
+
+    #[test]
+    fn decompression_stops_at_the_limit() {
+        let bomb = gzip(&vec![0; 1024 * 1024]);
+        assert!(bomb.len() < 4096);
+
+        assert_eq!(decode(&bomb, "gzip", 1024 * 1024).map(|b| b.len()), Some(1024 * 1024));
+        assert!(decode(&bomb, "gzip", 64 * 1024).is_none());
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::session::Session;
+
+pub const BODIES_DIR: &str = "bodies";
+pub const BODY_INDEX_FILE: &str = "index.jsonl";
+pub const ANALYZED_FILE: &str = "analyzed";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub at: u64,
+    pub url: String,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stored {
+    pub hash: String,
+    pub new_content: bool,
+    pub new_url: bool,
+}
+
+#[derive(Default)]
+struct Index {
+    analyzed: HashSet<String>,
+    pending: HashSet<String>,
+    urls: HashSet<(String, String)>,
+}
+
+// with a session the bodies and the index live in its database, the files are for --no-session
+enum Backend {
+    Files(PathBuf),
+    Session(Session),
+}
+
+pub struct ContentStore {
+    backend: Backend,
+    index: Mutex<Index>,
+}
+
+pub fn content_hash(body: &[u8]) -> String {
+    hex::encode(Sha256::digest(body))
+}
+
+fn read_lines(path: &Path) -> anyhow::Result<Vec<String>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut lines = vec![];
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            lines.push(line);
+        }
+    }
+    Ok(lines)
+}
+
+impl ContentStore {
+    // only content whose analysis finished is skipped by a new session; the url index just names it
+    pub fn open(dir: PathBuf) -> anyhow::Result<Self> {
+        let mut index = Index::default();
+
+        let path = dir.join(BODY_INDEX_FILE);
+        for line in read_lines(&path)? {
+            match serde_json::from_str::<IndexEntry>(&line) {
+                Ok(entry) => {
+                    index.urls.insert((entry.url, entry.hash));
+                }
+                Err(e) => eprintln!("⚠️ [Dedup] Linha inválida em {:?}: {}", path, e),
+            }
+        }
+
+        let analyzed = dir.join(ANALYZED_FILE);
+        index.analyzed.extend(read_lines(&analyzed)?.into_iter().map(|l| l.trim().to_string()));
+        if !index.analyzed.is_empty() {
+            println!(
+                "[Dedup] {} conteúdo(s) já analisado(s) carregado(s) de {:?}",
+                index.analyzed.len(),
+                analyzed
+            );
+        }
+
+        Ok(Self {
+            backend: Backend::Files(dir),
+            index: Mutex::new(index),
+        })
+    }
+
+    pub fn with_session(session: Session) -> anyhow::Result<Self> {
+        let index = Index {
+            analyzed: session.analyzed_bodies()?,
+            pending: HashSet::new(),
+            urls: session.body_urls()?,
+        };
+        if !index.analyzed.is_empty() {
+            println!("[Dedup] {} conteúdo(s) já analisado(s) carregado(s) da sessão", index.analyzed.len());
+        }
+
+        Ok(Self {
+            backend: Backend::Session(session),
+            index: Mutex::new(index),
+        })
+    }
+
+    pub fn body_path(dir: &Path, hash: &str) -> PathBuf {
+        dir.join(&hash[..2]).join(hash)
+    }
+
+    // new content is held as pending until `finish`, so a copy seen meanwhile is not analyzed twice
+    pub fn store(&self, url: &str, content_type: &str, body: &[u8]) -> anyhow::Result<Stored> {
+        let hash = content_hash(body);
+
+        let mut index = self.index.lock().unwrap();
+        let new_content = !index.analyzed.contains(&hash) && !index.pending.contains(&hash);
+        let new_url = !index.urls.contains(&(url.to_string(), hash.clone()));
+
+        match &self.backend {
+            Backend::Files(dir) => {
+                if new_content {
+                    let path = Self::body_path(dir, &hash);
+                    if !path.exists() {
+                        if let Some(parent) = path.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        fs::write(&path, body)?;
+                    }
+                }
+                if new_url {
+                    let entry = IndexEntry {
+                        at: SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|d| d.as_secs())
+                            .unwrap_or_default(),
+                        url: url.to_string(),
+                        hash: hash.clone(),
+                    };
+                    append(dir, BODY_INDEX_FILE, &serde_json::to_string(&entry)?)?;
+                }
+            }
+            Backend::Session(session) => {
+                if new_content || new_url {
+                    session.store_body(url, &hash, content_type, new_content.then(|| body.to_vec()));
+                }
+            }
+        }
+
+        if new_content {
+            index.pending.insert(hash.clone());
+        }
+        if new_url {
+            index.urls.insert((url.to_string(), hash.clone()));
+        }
+
+        Ok(Stored {
+            hash,
+            new_content,
+            new_url,
+        })
+    }
+
+    // a failed analysis leaves the content unmarked, so the next copy of it is analyzed again
+    pub fn finish(&self, hash: &str, analyzed: bool) -> anyhow::Result<()> {
+        let mut index = self.index.lock().unwrap();
+        index.pending.remove(hash);
+
+        if analyzed && index.analyzed.insert(hash.to_string()) {
+            match &self.backend {
+                Backend::Files(dir) => append(dir, ANALYZED_FILE, hash)?,
+                Backend::Session(session) => session.mark_analyzed(hash),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn append(dir: &Path, file: &str, line: &str) -> anyhow::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(dir.join(file))?;
+    writeln!(file, "{}", line)?;
+    Ok(())
+}
+
+// shared by every copy of an event that left Dedup; once the last copy is gone the content
+// counts as analyzed, unless a stage failed on it or it was dropped on the way
+pub struct Analysis {
+    store: Arc<ContentStore>,
+    hash: String,
+    failed: AtomicBool,
+}
+
+impl Analysis {
+    pub fn new(store: Arc<ContentStore>, hash: String) -> Self {
+        Self {
+            store,
+            hash,
+            failed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn fail(&self) {
+        self.failed.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Drop for Analysis {
+    fn drop(&mut self) {
+        let analyzed = !self.failed.load(Ordering::SeqCst);
+        if let Err(e) = self.store.finish(&self.hash, analyzed) {
+            eprintln!("❌ [Dedup] Erro ao marcar {} como analisado: {}", &self.hash[..12], e);
+        }
+    }
+}
+
+impl fmt::Debug for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Analysis").field("hash", &self.hash).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mboitata-content-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn content_is_skipped_only_after_its_analysis_finished() {
+        let dir = temp_dir("finish");
+        let store = Arc::new(ContentStore::open(dir.clone()).unwrap());
+
+        let first = store.store("https://a.test/app.js", "text/javascript", b"body").unwrap();
+        assert!(first.new_content && first.new_url);
+        assert!(ContentStore::body_path(&dir, &first.hash).exists());
+
+        // still being analyzed: a copy under another url is not sent again
+        let copy = store.store("https://b.test/app.js", "text/javascript", b"body").unwrap();
+        assert!(!copy.new_content && copy.new_url);
+
+        drop(Analysis::new(store.clone(), first.hash.clone()));
+
+        let resumed = ContentStore::open(dir.clone()).unwrap();
+        let again = resumed.store("https://a.test/app.js", "text/javascript", b"body").unwrap();
+        assert!(!again.new_content && !again.new_url);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn failed_analysis_is_retried() {
+        let dir = temp_dir("failed");
+        let store = Arc::new(ContentStore::open(dir.clone()).unwrap());
+
+        let first = store.store("https://a.test/app.js", "text/javascript", b"body").unwrap();
+        let analysis = Analysis::new(store.clone(), first.hash);
+        analysis.fail();
+        drop(analysis);
+
+        assert!(store.store("https://a.test/app.js", "text/javascript", b"body").unwrap().new_content);
+
+        // killed before the analysis finished
+        let resumed = ContentStore::open(dir.clone()).unwrap();
+        assert!(resumed.store("https://a.test/app.js", "text/javascript", b"body").unwrap().new_content);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn session_backed_store_keeps_bodies_and_markers_in_the_database() {
+        let dir = temp_dir("session");
+        let session = Session::open(&dir.join("session.db")).unwrap();
+        let store = Arc::new(ContentStore::with_session(session.clone()).unwrap());
+
+        let first = store.store("https://a.test/app.js", "text/javascript", b"body").unwrap();
+        store.store("https://b.test/app.js", "text/javascript", b"body").unwrap();
+        drop(Analysis::new(store.clone(), first.hash.clone()));
+        session.flush();
+
+        assert_eq!(session.body(&first.hash).unwrap().as_deref(), Some(&b"body"[..]));
+        assert!(!dir.join(BODIES_DIR).exists());
+
+        let resumed = ContentStore::with_session(session).unwrap();
+        let again = resumed.store("https://b.test/app.js", "text/javascript", b"body").unwrap();
+        assert!(!again.new_content && !again.new_url);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use tokio::{sync::Semaphore, time::timeout};
+
+pub const DEFAULT_MAX_ANALYSIS_SIZE: usize = 20 * 1024 * 1024;
+pub const DEFAULT_ANALYSIS_TIMEOUT: u64 = 30;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CpuLimits {
+    pub workers: usize,
+    pub max_size: usize,
+    pub timeout: Duration,
+}
+
+impl Default for CpuLimits {
+    fn default() -> Self {
+        Self {
+            workers: default_workers(),
+            max_size: DEFAULT_MAX_ANALYSIS_SIZE,
+            timeout: Duration::from_secs(DEFAULT_ANALYSIS_TIMEOUT),
+        }
+    }
+}
+
+pub fn default_workers() -> usize {
+    thread::available_parallelism().map_or(4, |n| n.get())
+}
+
+// parsing, regex and sourcemap work runs here instead of on the runtime workers that serve the proxy
+#[derive(Clone)]
+pub struct CpuPool {
+    permits: Arc<Semaphore>,
+    limits: CpuLimits,
+    stuck: Arc<AtomicUsize>,
+}
+
+impl Default for CpuPool {
+    fn default() -> Self {
+        Self::new(CpuLimits::default())
+    }
+}
+
+impl CpuPool {
+    pub fn new(limits: CpuLimits) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(limits.workers.max(1))),
+            limits,
+            stuck: Arc::default(),
+        }
+    }
+
+    pub fn limits(&self) -> CpuLimits {
+        self.limits
+    }
+
+    // jobs past their timeout whose thread is still running
+    pub fn stuck(&self) -> usize {
+        self.stuck.load(Ordering::SeqCst)
+    }
+
+    // Ok(None) means the input was over the size limit and was skipped; `size` is the decoded size
+    pub async fn run<T, F>(&self, name: &str, size: usize, job: F) -> anyhow::Result<Option<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        if size > self.limits.max_size {
+            println!(
+                "⚠️ [Cpu] Ignorando {name}: {size} bytes, acima do limite de {} bytes",
+                self.limits.max_size
+            );
+            return Ok(None);
+        }
+
+        let permit = self.permits.clone().acquire_owned().await?;
+        // a blocking task can't be cancelled, so the permit is only released when the job really ends
+        let mut task = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            job()
+        });
+
+        match timeout(self.limits.timeout, &mut task).await {
+            Ok(joined) => Ok(Some(joined?)),
+            Err(_) => {
+                let stuck = self.stuck.fetch_add(1, Ordering::SeqCst) + 1;
+                eprintln!(
+                    "⚠️ [Cpu] {name} continua rodando após o timeout. {stuck} de {} worker(s) presos.",
+                    self.limits.workers.max(1)
+                );
+
+                let pool_stuck = self.stuck.clone();
+                tokio::spawn(async move {
+                    let _ = task.await;
+                    pool_stuck.fetch_sub(1, Ordering::SeqCst);
+                });
+
+                anyhow::bail!(
+                    "análise de {name} excedeu {}s",
+                    self.limits.timeout.as_secs()
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(timeout: Duration) -> CpuPool {
+        CpuPool::new(CpuLimits {
+            workers: 1,
+            max_size: 10,
+            timeout,
+        })
+    }
+
+    #[tokio::test]
+    async fn oversized_input_is_skipped() {
+        let pool = pool(Duration::from_secs(1));
+        assert_eq!(pool.run("big", 11, || 1).await.unwrap(), None);
+        assert_eq!(pool.run("small", 10, || 1).await.unwrap(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn timed_out_jobs_are_counted_until_they_end() {
+        let pool = pool(Duration::from_millis(50));
+
+        let err = pool
+            .run("slow", 1, || thread::sleep(Duration::from_millis(300)))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("excedeu"));
+        assert_eq!(pool.stuck(), 1);
+
+        // the stuck job still holds the only worker
+        assert_eq!(pool.run("next", 1, || 1).await.unwrap(), Some(1));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(pool.stuck(), 0);
+    }
+}
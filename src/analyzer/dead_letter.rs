@@ -0,0 +1,76 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::{intercepted::InterceptedResponse, stage::StageId};
+
+pub const DEAD_LETTER_FILE: &str = "dead-letter.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub at: u64,
+    pub stage: String,
+    pub url: String,
+    pub error: String,
+    pub response: InterceptedResponse,
+}
+
+pub struct DeadLetters {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl DeadLetters {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn record(&self, stage: StageId, resp: InterceptedResponse, error: &str) {
+        let url = resp.location();
+
+        eprintln!("☠️ [{stage}] Falha em {url}: {error}");
+
+        let letter = DeadLetter {
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            stage: stage.to_string(),
+            url,
+            error: error.to_string(),
+            response: resp,
+        };
+
+        let line = match serde_json::to_string(&letter) {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("Erro ao serializar dead letter de {}: {}", letter.url, e);
+                return;
+            }
+        };
+
+        let _guard = self.lock.lock().unwrap();
+        if let Some(parent) = self.path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{line}"));
+
+        if let Err(e) = written {
+            eprintln!("Erro ao salvar o arquivo {:?}: {}", self.path, e);
+        }
+    }
+}
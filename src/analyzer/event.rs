@@ -1,36 +1,332 @@
-use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
-use tokio::sync::{broadcast, mpsc::UnboundedSender, Notify};
-use crate::analyzer::{intercepted::InterceptedResponse, stage::StageId};
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{
+    Notify, OwnedSemaphorePermit, Semaphore, broadcast,
+    mpsc::{Sender, error::TrySendError},
+};
+
+use crate::analyzer::{
+    finding::Finding, intercepted::InterceptedResponse, pipeline::Route, sink::FindingSinks,
+    spill::SpillQueue, stage::StageId,
+};
+
+pub const CAPTURED: &str = "proxy";
+
+pub type EventId = u64;
+
+// seeded from the clock so a resumed session doesn't reuse ids from the previous run
+fn next_event_id() -> EventId {
+    static NEXT: LazyLock<AtomicU64> = LazyLock::new(|| {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        AtomicU64::new(millis << 16)
+    });
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hop {
+    pub id: EventId,
+    pub url: String,
+    pub origin: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Provenance {
+    pub id: EventId,
+    pub parent: Option<EventId>,
+    pub origin: String,
+    pub page: Option<String>,
+    pub chain: Vec<Hop>,
+}
+
+impl Provenance {
+    pub fn captured(page: Option<String>) -> Self {
+        Self {
+            id: next_event_id(),
+            parent: None,
+            origin: CAPTURED.to_string(),
+            page,
+            chain: vec![],
+        }
+    }
+
+    // `url` is this event's own url, it becomes the last hop of the child's chain
+    pub fn child(&self, url: impl Into<String>, origin: StageId) -> Self {
+        let mut chain = self.chain.clone();
+        chain.push(Hop {
+            id: self.id,
+            url: url.into(),
+            origin: self.origin.clone(),
+        });
+
+        Self {
+            id: next_event_id(),
+            parent: Some(self.id),
+            origin: origin.to_string(),
+            page: self.page.clone(),
+            chain,
+        }
+    }
+
+    pub fn trail(&self, url: &str) -> Vec<String> {
+        let mut trail: Vec<String> = self.page.iter().cloned().collect();
+        for hop in self.chain.iter().map(|h| h.url.as_str()).chain([url]) {
+            if trail.last().map(String::as_str) != Some(hop) {
+                trail.push(hop.to_string());
+            }
+        }
+        trail
+    }
+}
 
 pub struct Event {
     pub stage: StageId,
     pub resp: InterceptedResponse,
 }
 
+// what a stage emitted while it ran, sent on only after it has given back its permits
+#[derive(Clone)]
+pub struct Outbox {
+    events: Arc<Mutex<Vec<Event>>>,
+    limit: usize,
+    lease: Arc<tokio::sync::Mutex<Lease>>,
+    paused: Arc<Mutex<(Duration, Option<Instant>)>>,
+}
+
+// the worker and global slot the stage runs under, given back while an overflow is forwarded
+pub struct Lease {
+    workers: Arc<Semaphore>,
+    global: Arc<Semaphore>,
+    held: Option<(OwnedSemaphorePermit, OwnedSemaphorePermit)>,
+}
+
+impl Lease {
+    pub fn new(
+        workers: Arc<Semaphore>,
+        global: Arc<Semaphore>,
+        held: (OwnedSemaphorePermit, OwnedSemaphorePermit),
+    ) -> Self {
+        Self {
+            workers,
+            global,
+            held: Some(held),
+        }
+    }
+}
+
+impl Outbox {
+    pub fn new(limit: usize, lease: Lease) -> Self {
+        Self {
+            events: Arc::new(Mutex::new(vec![])),
+            limit: limit.max(1),
+            lease: Arc::new(tokio::sync::Mutex::new(lease)),
+            paused: Arc::new(Mutex::new((Duration::ZERO, None))),
+        }
+    }
+
+    // once the limit is reached everything held so far is handed back to be forwarded
+    fn push(&self, event: Event) -> Option<Vec<Event>> {
+        let mut events = self.events.lock().unwrap();
+        events.push(event);
+        (events.len() >= self.limit).then(|| mem::take(&mut *events))
+    }
+
+    pub fn take(&self) -> Vec<Event> {
+        mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    pub async fn release(&self) {
+        self.lease.lock().await.held = None;
+    }
+
+    // time spent forwarding an overflow, which the stage timeout doesn't count
+    pub fn paused(&self) -> Duration {
+        let (total, since) = *self.paused.lock().unwrap();
+        total + since.map_or(Duration::ZERO, |since| since.elapsed())
+    }
+
+    // the stage waits on the queues like any producer, with no permit a consumer could need
+    async fn overflow(&self, dispatcher: &Dispatcher, events: Vec<Event>) {
+        let mut lease = self.lease.lock().await;
+        self.paused.lock().unwrap().1 = Some(Instant::now());
+        lease.held = None;
+
+        for event in events {
+            dispatcher.deliver(event.stage, event.resp).await;
+        }
+
+        let workers = lease.workers.clone().acquire_owned().await;
+        let global = lease.global.clone().acquire_owned().await;
+        if let (Ok(worker), Ok(slot)) = (workers, global) {
+            lease.held = Some((worker, slot));
+        }
+
+        let mut paused = self.paused.lock().unwrap();
+        if let Some(since) = paused.1.take() {
+            paused.0 += since.elapsed();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum QueuePolicy {
+    Block,
+    Drop,
+    Spill,
+}
+
+pub struct StageQueue {
+    pub tx: Sender<Event>,
+    pub spill: Option<Arc<SpillQueue>>,
+    pub dropped: AtomicU64,
+}
+
+impl StageQueue {
+    pub fn depth(&self) -> usize {
+        let buffered = self.tx.max_capacity() - self.tx.capacity();
+        buffered + self.spill.as_ref().map_or(0, |s| s.pending())
+    }
+}
+
 #[derive(Clone)]
 pub struct Dispatcher {
-    tx: UnboundedSender<Event>,
+    queues: Arc<HashMap<StageId, StageQueue>>,
+    routes: Arc<Vec<Route>>,
+    findings: Arc<FindingSinks>,
+    policy: QueuePolicy,
     closed: broadcast::Sender<()>,
     inflight: Arc<AtomicUsize>,
     notify_done: Arc<Notify>,
+    outbox: Option<Outbox>,
 }
 
 impl Dispatcher {
-    pub fn new(tx: UnboundedSender<Event>, closed: broadcast::Sender<()>) -> Self {
+    pub fn new(
+        queues: HashMap<StageId, StageQueue>,
+        routes: Vec<Route>,
+        findings: FindingSinks,
+        policy: QueuePolicy,
+        closed: broadcast::Sender<()>,
+    ) -> Self {
         Self {
-            tx,
+            queues: Arc::new(queues),
+            routes: Arc::new(routes),
+            findings: Arc::new(findings),
+            policy,
             closed,
             inflight: Arc::new(AtomicUsize::new(0)),
             notify_done: Arc::new(Notify::new()),
+            outbox: None,
         }
     }
 
-    pub fn emit(&self, stage: StageId, resp: InterceptedResponse) {
-        self.inflight.fetch_add(1, Ordering::SeqCst);
+    // a dispatcher whose emits are collected instead of waiting on downstream queues
+    pub fn buffered(&self, outbox: Outbox) -> Self {
+        Self {
+            outbox: Some(outbox),
+            ..self.clone()
+        }
+    }
+
+    pub fn outbox(&self) -> Option<Outbox> {
+        self.outbox.clone()
+    }
+
+    pub async fn emit(&self, stage: StageId, resp: InterceptedResponse) {
+        match &self.outbox {
+            Some(outbox) => {
+                if let Some(events) = outbox.push(Event { stage, resp }) {
+                    outbox.overflow(self, events).await;
+                }
+            }
+            None => self.deliver(stage, resp).await,
+        }
+    }
+
+    async fn deliver(&self, stage: StageId, resp: InterceptedResponse) {
+        let Some(queue) = self.queues.get(&stage) else {
+            return;
+        };
 
+        self.inflight.fetch_add(1, Ordering::SeqCst);
+        let analysis = resp.analysis.clone();
         let event = Event { stage, resp };
-        let _ = self.tx.send(event);
+        let mut spilled = false;
+
+        let delivered = match (self.policy, &queue.spill) {
+            (QueuePolicy::Block, _) => queue.tx.send(event).await.is_ok(),
+            // once something is on disk, keep appending there so the stage sees events in order
+            (QueuePolicy::Spill, Some(spill)) if spill.pending() > 0 => {
+                spilled = true;
+                push_spill(spill, &event)
+            }
+            (QueuePolicy::Spill, Some(spill)) => match queue.tx.try_send(event) {
+                Ok(_) => true,
+                Err(TrySendError::Full(event)) => {
+                    spilled = true;
+                    push_spill(spill, &event)
+                }
+                Err(TrySendError::Closed(_)) => false,
+            },
+            _ => match queue.tx.try_send(event) {
+                Ok(_) => true,
+                Err(TrySendError::Full(_)) => {
+                    let dropped = queue.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                    if dropped == 1 || dropped % 100 == 0 {
+                        eprintln!(
+                            "⚠️ [Queue] Fila do stage {stage} cheia. {dropped} evento(s) descartado(s)."
+                        );
+                    }
+                    false
+                }
+                Err(TrySendError::Closed(_)) => false,
+            },
+        };
+
+        if !delivered {
+            self.complete();
+        }
+        // on disk or dropped, the event no longer finishes its content's analysis
+        if let Some(analysis) = analysis.filter(|_| !delivered || spilled) {
+            analysis.fail();
+        }
+    }
+
+    pub async fn forward(&self, from: StageId, port: &str, resp: InterceptedResponse) {
+        let targets = self
+            .routes
+            .iter()
+            .filter(|r| r.from == from && r.port == port && r.when.matches(&resp))
+            .map(|r| r.to)
+            .collect::<Vec<_>>();
+
+        // clones only copy the headers, the body is shared; the last target takes the original
+        let Some((last, rest)) = targets.split_last() else {
+            return;
+        };
+        for to in rest {
+            self.emit(*to, resp.clone()).await;
+        }
+        self.emit(*last, resp).await;
+    }
+
+    // findings skip the stage queues and go straight to the sinks
+    pub fn report(&self, finding: Finding) {
+        self.findings.report(finding);
     }
 
     pub fn complete(&self) {
@@ -39,6 +335,16 @@ impl Dispatcher {
         }
     }
 
+    pub fn queue_depths(&self) -> Vec<(StageId, usize, u64)> {
+        let mut depths = self
+            .queues
+            .iter()
+            .map(|(id, q)| (*id, q.depth(), q.dropped.load(Ordering::Relaxed)))
+            .collect::<Vec<_>>();
+        depths.sort_by_key(|(id, _, _)| id.to_string());
+        depths
+    }
+
     pub async fn close_gracefully(&self) {
         while self.inflight.load(Ordering::SeqCst) > 0 {
             self.notify_done.notified().await;
@@ -49,3 +355,50 @@ impl Dispatcher {
     }
 }
 
+fn push_spill(spill: &SpillQueue, event: &Event) -> bool {
+    match spill.push(&event.resp) {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!(
+                "❌ [Queue] Erro ao mover evento do stage {} para o disco: {}",
+                event.stage, e
+            );
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trail_starts_at_the_page_and_ends_at_the_event() {
+        let captured = Provenance::captured(Some("https://a.test/".into()));
+        let source_map = captured.child("https://a.test/app.js", StageId::Map);
+        let source = source_map.child("https://a.test/app.js.map", StageId::Map);
+
+        assert_eq!(captured.trail("https://a.test/app.js"), ["https://a.test/", "https://a.test/app.js"]);
+        assert_eq!(
+            source.trail("webpack:///src/api.ts"),
+            [
+                "https://a.test/",
+                "https://a.test/app.js",
+                "https://a.test/app.js.map",
+                "webpack:///src/api.ts"
+            ]
+        );
+        assert_eq!(source.parent, Some(source_map.id));
+        assert_eq!(source.chain[0].id, captured.id);
+    }
+
+    #[test]
+    fn trail_skips_repeated_hops() {
+        // the page itself is the captured document
+        let captured = Provenance::captured(Some("https://a.test/".into()));
+        assert_eq!(captured.trail("https://a.test/"), ["https://a.test/"]);
+
+        let without_page = Provenance::captured(None);
+        assert_eq!(without_page.trail("https://a.test/app.js"), ["https://a.test/app.js"]);
+    }
+}
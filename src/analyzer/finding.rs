@@ -0,0 +1,192 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::{event::EventId, intercepted::InterceptedResponse, stage::StageId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn from_offset(text: &str, offset: usize) -> Self {
+        Locator::new(text).locate(offset)
+    }
+}
+
+// walks forward through a text, so locating offsets in increasing order costs a single pass
+pub struct Locator<'a> {
+    text: &'a str,
+    offset: usize,
+    position: Position,
+}
+
+impl<'a> Locator<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            offset: 0,
+            position: Position { line: 1, column: 1 },
+        }
+    }
+
+    pub fn locate(&mut self, offset: usize) -> Position {
+        if offset < self.offset {
+            *self = Self::new(self.text);
+        }
+
+        let offset = offset.min(self.text.len());
+        let Some(skipped) = self.text.get(self.offset..offset) else {
+            return self.position;
+        };
+
+        for c in skipped.chars() {
+            if c == '\n' {
+                self.position.line += 1;
+                self.position.column = 1;
+            } else {
+                self.position.column += 1;
+            }
+        }
+        self.offset = offset;
+        self.position
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub kind: String,
+    pub value: String,
+    pub source: String,
+    pub host: String,
+    pub stage: String,
+    pub position: Option<Position>,
+    pub confidence: Confidence,
+    pub detail: Option<String>,
+    #[serde(default)]
+    pub event: EventId,
+    #[serde(default)]
+    pub parent: Option<EventId>,
+    #[serde(default)]
+    pub trail: Vec<String>,
+}
+
+impl Finding {
+    pub fn new(
+        stage: StageId,
+        kind: impl Into<String>,
+        value: impl Into<String>,
+        resp: &InterceptedResponse,
+    ) -> Self {
+        let source = resp.location();
+        let trail = resp.provenance.trail(&source);
+
+        Self {
+            kind: kind.into(),
+            value: value.into(),
+            source,
+            host: resp.host.clone(),
+            stage: stage.to_string(),
+            position: None,
+            confidence: Confidence::Medium,
+            detail: None,
+            event: resp.provenance.id,
+            parent: resp.provenance.parent,
+            trail,
+        }
+    }
+
+    // about a host rather than a response, e.g. what the proxy itself noticed
+    pub fn for_host(origin: &str, kind: impl Into<String>, value: impl Into<String>, host: &str) -> Self {
+        Self {
+            kind: kind.into(),
+            value: value.into(),
+            source: host.to_string(),
+            host: host.to_string(),
+            stage: origin.to_string(),
+            position: None,
+            confidence: Confidence::Medium,
+            detail: None,
+            event: 0,
+            parent: None,
+            trail: vec![host.to_string()],
+        }
+    }
+
+    pub fn at(mut self, text: &str, offset: usize) -> Self {
+        self.position = Some(Position::from_offset(text, offset));
+        self
+    }
+
+    pub fn position(mut self, position: Option<Position>) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn confidence(mut self, confidence: Confidence) -> Self {
+        self.confidence = confidence;
+        self
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn explain(&self) -> String {
+        self.trail.join(" → ")
+    }
+
+    pub fn location(&self) -> String {
+        match self.position {
+            Some(p) => format!("{}:{}:{}", self.source, p.line, p.column),
+            None => self.source.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locates_lines_and_character_columns() {
+        let text = "ab\nçé = 'x'\n\nlast";
+        let position = |line, column| Position { line, column };
+
+        assert_eq!(Position::from_offset(text, 0), position(1, 1));
+        assert_eq!(Position::from_offset(text, 2), position(1, 3));
+        assert_eq!(Position::from_offset(text, 3), position(2, 1));
+        assert_eq!(Position::from_offset(text, text.find('=').unwrap()), position(2, 4));
+        assert_eq!(Position::from_offset(text, text.find("last").unwrap()), position(4, 1));
+    }
+
+    #[test]
+    fn locator_matches_a_full_rescan_in_any_order() {
+        let text = "const a = 'https://a.example.com';\nconst b = 'https://b.example.com'; // ü\n";
+        let offsets = [45, 0, 11, 11, 36, 70, text.len()];
+
+        let naive = |offset: usize| {
+            let before = &text[..offset];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            Position {
+                line: before.matches('\n').count() + 1,
+                column: before[line_start..].chars().count() + 1,
+            }
+        };
+
+        let mut locator = Locator::new(text);
+        for offset in offsets {
+            assert_eq!(locator.locate(offset), naive(offset), "offset {offset}");
+        }
+    }
+}
@@ -1,19 +1,115 @@
 use hyper::HeaderMap;
 use sanitize_filename::sanitize;
+use serde::{Deserialize, Serialize};
 use std::{
-    io::Read,
+    fmt,
+    net::SocketAddr,
     path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
 use url::Url;
 
-#[derive(Debug, Clone)]
+use crate::analyzer::{body::Body, content_store::Analysis, event::Provenance, stage::StageId, stages::js::analyzer::JsAst};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Timings {
+    pub dns: Option<Duration>,
+    pub connect: Option<Duration>,
+    pub tls: Option<Duration>,
+    pub ttfb: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionInfo {
+    pub client_addr: Option<SocketAddr>,
+    pub upstream_addr: Option<SocketAddr>,
+    pub tls_version: Option<String>,
+    pub tls_cipher: Option<String>,
+    pub timings: Timings,
+}
+
+impl fmt::Display for ConnectionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn addr(a: &Option<SocketAddr>) -> String {
+            a.map(|a| a.to_string()).unwrap_or_else(|| "-".into())
+        }
+        fn ms(d: &Option<Duration>) -> String {
+            d.map(|d| format!("{}ms", d.as_millis()))
+                .unwrap_or_else(|| "-".into())
+        }
+
+        write!(
+            f,
+            "client={} upstream={} tls={} cipher={} dns={} connect={} tls_hs={} ttfb={} total={}",
+            addr(&self.client_addr),
+            addr(&self.upstream_addr),
+            self.tls_version.as_deref().unwrap_or("-"),
+            self.tls_cipher.as_deref().unwrap_or("-"),
+            ms(&self.timings.dns),
+            ms(&self.timings.connect),
+            ms(&self.timings.tls),
+            ms(&self.timings.ttfb),
+            ms(&self.timings.total),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExchangeRecord {
+    pub method: String,
+    pub url: String,
+    pub request_headers: Vec<(String, String)>,
+    #[serde(with = "base64_bytes")]
+    pub request_body: Vec<u8>,
+    pub status: u16,
+    pub response_headers: Vec<(String, String)>,
+}
+
+pub fn url_host(host: &str) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{host}]")
+    } else {
+        host.to_string()
+    }
+}
+
+pub fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(k, v)| (k.to_string(), String::from_utf8_lossy(v.as_bytes()).into_owned()))
+        .collect()
+}
+
+pub mod base64_bytes {
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InterceptedResponse {
     pub scheme: String,
     pub host: String,
     pub path: String,
     pub content_encoding: String,
     pub content_type: String,
-    pub body: Vec<u8>,
+    pub body: Body,
+    pub conn: ConnectionInfo,
+    pub exchange: Option<Arc<ExchangeRecord>>,
+    #[serde(default)]
+    pub provenance: Provenance,
+    #[serde(skip)]
+    pub analysis: Option<Arc<Analysis>>,
 }
 
 impl InterceptedResponse {
@@ -22,7 +118,8 @@ impl InterceptedResponse {
         scheme: String,
         host: String,
         headers: &HeaderMap,
-        body: Vec<u8>,
+        body: impl Into<Body>,
+        conn: ConnectionInfo,
     ) -> Self {
         let content_type = headers
             .get("content-type")
@@ -42,7 +139,44 @@ impl InterceptedResponse {
             host,
             content_encoding,
             content_type,
-            body,
+            body: body.into(),
+            conn,
+            exchange: None,
+            provenance: Provenance::captured(None),
+            analysis: None,
+        }
+    }
+
+    // a new event produced by `origin` out of this one
+    pub fn derive(&self, origin: StageId, path: String, content_type: &str, body: impl Into<Body>) -> Self {
+        self.derive_with(self.provenance.child(self.location(), origin), path, content_type, body)
+    }
+
+    pub fn derive_with(
+        &self,
+        provenance: Provenance,
+        path: String,
+        content_type: &str,
+        body: impl Into<Body>,
+    ) -> Self {
+        InterceptedResponse {
+            scheme: self.scheme.clone(),
+            host: self.host.clone(),
+            path,
+            content_encoding: "identity".into(),
+            content_type: content_type.to_string(),
+            body: body.into(),
+            conn: ConnectionInfo::default(),
+            exchange: None,
+            provenance,
+            analysis: self.analysis.clone(),
+        }
+    }
+
+    // the content this event came from must be analyzed again by a later session
+    pub fn fail_analysis(&self) {
+        if let Some(analysis) = &self.analysis {
+            analysis.fail();
         }
     }
 
@@ -72,29 +206,34 @@ impl InterceptedResponse {
     }
 
     pub fn get_url(&self) -> Result<Url, url::ParseError> {
-        let full_url = format!("{}://{}{}", self.scheme, self.host, self.path);
+        let full_url = format!("{}://{}{}", self.scheme, url_host(&self.host), self.path);
         Url::parse(&full_url)
     }
 
-    pub fn get_body(&self) -> Option<String> {
-        match self.content_encoding.as_str() {
-            "gzip" => {
-                let mut gz = flate2::read::GzDecoder::new(self.body.as_slice());
-                let mut s = String::new();
-                gz.read_to_string(&mut s).ok();
-                Some(s)
-            }
-            "br" => {
-                let mut decoder = brotli::Decompressor::new(self.body.as_slice(), 4096);
-                let mut s = String::new();
-                decoder.read_to_string(&mut s).ok();
-                Some(s)
-            }
-            "identity" | "" => String::from_utf8(self.body.to_vec()).ok(),
-            other => {
-                println!("⚠️ Encoding não suportado: {}", other);
-                None
-            }
-        }
+    pub fn location(&self) -> String {
+        self.get_url()
+            .map(|u| u.to_string())
+            .unwrap_or_else(|_| format!("{}{}", self.host, self.path))
+    }
+
+    pub fn trail(&self) -> Vec<String> {
+        self.provenance.trail(&self.location())
+    }
+
+    pub fn get_body(&self) -> Option<&str> {
+        self.body.text(&self.content_encoding)
+    }
+
+    pub fn decoded_body(&self) -> Option<&[u8]> {
+        self.body.decoded(&self.content_encoding).map(|b| b.as_ref())
+    }
+
+    // what analysis actually works on, not what came over the wire
+    pub fn decoded_len(&self) -> usize {
+        self.decoded_body().map_or(self.body.len(), <[u8]>::len)
+    }
+
+    pub fn js_ast(&self) -> anyhow::Result<Arc<JsAst>> {
+        self.body.ast(&self.content_encoding, &self.path)
     }
 }
@@ -1,7 +1,15 @@
 pub mod stages;
 
+pub mod body;
+pub mod content_store;
+pub mod cpu;
+pub mod dead_letter;
 pub mod event;
+pub mod finding;
 pub mod intercepted;
+pub mod pipeline;
 pub mod router;
 pub mod setup;
+pub mod sink;
+pub mod spill;
 pub mod stage;
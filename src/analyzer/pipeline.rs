@@ -0,0 +1,392 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::analyzer::{
+    intercepted::InterceptedResponse, stage::StageId, stages::plugin::PluginConfig,
+};
+
+pub const OUT: &str = "out";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub stages: HashMap<String, StageConfig>,
+    pub plugins: HashMap<String, PluginConfig>,
+    pub routes: Option<Vec<RouteConfig>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StageConfig {
+    pub enabled: bool,
+    pub workers: Option<usize>,
+    pub timeout_secs: Option<u64>,
+    pub options: toml::Table,
+}
+
+impl Default for StageConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            workers: None,
+            timeout_secs: None,
+            options: toml::Table::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    pub from: String,
+    #[serde(default = "default_port")]
+    pub port: String,
+    pub to: String,
+    #[serde(default)]
+    pub when: Condition,
+}
+
+fn default_port() -> String {
+    OUT.to_string()
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Condition {
+    pub content_type: Vec<String>,
+    pub not_content_type: Vec<String>,
+    pub host: Vec<String>,
+    pub not_host: Vec<String>,
+    pub min_size: Option<usize>,
+    pub max_size: Option<usize>,
+    pub exchange: Option<bool>,
+}
+
+impl Condition {
+    pub fn matches(&self, resp: &InterceptedResponse) -> bool {
+        let content_type = resp.content_type.to_ascii_lowercase();
+        // the limits are on what the stages read, a compressed body on the wire says little about that
+        let size = resp.decoded_len();
+
+        (self.content_type.is_empty() || contains_any(&content_type, &self.content_type))
+            && !contains_any(&content_type, &self.not_content_type)
+            && (self.host.is_empty() || host_matches(&resp.host, &self.host))
+            && !host_matches(&resp.host, &self.not_host)
+            && self.min_size.is_none_or(|min| size >= min)
+            && self.max_size.is_none_or(|max| size <= max)
+            && self.exchange.is_none_or(|e| e == resp.exchange.is_some())
+    }
+}
+
+fn contains_any(value: &str, needles: &[String]) -> bool {
+    needles
+        .iter()
+        .any(|n| value.contains(n.to_ascii_lowercase().as_str()))
+}
+
+fn host_matches(host: &str, domains: &[String]) -> bool {
+    let host = host.to_ascii_lowercase();
+    domains.iter().any(|d| {
+        let d = d.trim_start_matches("*.").to_ascii_lowercase();
+        host == d || host.ends_with(&format!(".{d}"))
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct Route {
+    pub from: StageId,
+    pub port: String,
+    pub to: StageId,
+    pub when: Condition,
+}
+
+impl Route {
+    fn new(from: StageId, port: &str, to: StageId, when: Condition) -> Self {
+        Self {
+            from,
+            port: port.to_string(),
+            to,
+            when,
+        }
+    }
+}
+
+impl PipelineConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content =
+            fs::read_to_string(path).map_err(|e| anyhow!("não foi possível ler {:?}: {}", path, e))?;
+        let config: Self =
+            toml::from_str(&content).map_err(|e| anyhow!("pipeline {:?} inválido: {}", path, e))?;
+
+        for name in config.plugins.keys() {
+            if name.parse::<StageId>().is_ok() {
+                bail!("o plugin {name} usa o nome de um stage embutido");
+            }
+        }
+        for name in config.stages.keys() {
+            config.resolve(name)?;
+        }
+        if !config.is_enabled(StageId::Filter) {
+            bail!("o stage filter é a entrada do pipeline e não pode ser desativado");
+        }
+        config.routes()?;
+
+        Ok(config)
+    }
+
+    pub fn resolve(&self, name: &str) -> anyhow::Result<StageId> {
+        match name.parse() {
+            Ok(id) => Ok(id),
+            Err(_) if self.plugins.contains_key(name.trim()) => Ok(StageId::plugin(name.trim())),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn plugins(&self) -> impl Iterator<Item = (StageId, &PluginConfig)> {
+        self.plugins
+            .iter()
+            .map(|(name, plugin)| (StageId::plugin(name), plugin))
+    }
+
+    fn stage(&self, id: StageId) -> Option<&StageConfig> {
+        self.stages
+            .iter()
+            .find(|(name, _)| self.resolve(name).is_ok_and(|n| n == id))
+            .map(|(_, stage)| stage)
+    }
+
+    pub fn is_enabled(&self, id: StageId) -> bool {
+        self.stage(id).is_none_or(|s| s.enabled)
+    }
+
+    pub fn stage_workers(&self) -> Vec<(StageId, usize)> {
+        self.stages
+            .iter()
+            .filter_map(|(name, stage)| Some((self.resolve(name).ok()?, stage.workers?)))
+            .collect()
+    }
+
+    pub fn stage_timeouts(&self) -> Vec<(StageId, Duration)> {
+        self.stages
+            .iter()
+            .filter_map(|(name, stage)| {
+                Some((self.resolve(name).ok()?, Duration::from_secs(stage.timeout_secs?)))
+            })
+            .collect()
+    }
+
+    pub fn options<T: DeserializeOwned + Default>(&self, id: StageId) -> anyhow::Result<T> {
+        match self.stage(id) {
+            Some(stage) if !stage.options.is_empty() => toml::Value::Table(stage.options.clone())
+                .try_into()
+                .map_err(|e| anyhow!("opções inválidas para o stage {id}: {e}")),
+            _ => Ok(T::default()),
+        }
+    }
+
+    pub fn routes(&self) -> anyhow::Result<Vec<Route>> {
+        let routes = match &self.routes {
+            Some(routes) => routes.iter().map(|r| self.route(r)).collect::<anyhow::Result<Vec<_>>>()?,
+            None => self.bypass_disabled_dedup(default_routes()),
+        };
+
+        check_cycles(&routes)?;
+        Ok(routes)
+    }
+
+    // a typo here would otherwise silently disconnect part of the analysis
+    fn route(&self, route: &RouteConfig) -> anyhow::Result<Route> {
+        let from = self.resolve(&route.from)?;
+        let to = self.resolve(&route.to)?;
+
+        if let Some(ports) = from.ports() {
+            if ports.is_empty() {
+                bail!("o stage {from} não encaminha eventos e não pode ser origem de uma rota");
+            }
+            if !ports.contains(&route.port.as_str()) {
+                bail!(
+                    "o stage {from} não tem a porta '{}' (portas: {})",
+                    route.port,
+                    ports.join(", ")
+                );
+            }
+        }
+
+        for id in [from, to] {
+            if !self.is_enabled(id) {
+                bail!("a rota {from}.{} → {to} usa o stage {id}, que está desativado", route.port);
+            }
+        }
+
+        Ok(Route::new(from, &route.port, to, route.when.clone()))
+    }
+
+    // without dedup the analysis stages hang straight off the filter
+    fn bypass_disabled_dedup(&self, routes: Vec<Route>) -> Vec<Route> {
+        if self.is_enabled(StageId::Dedup) {
+            return routes;
+        }
+
+        routes
+            .into_iter()
+            .filter(|r| r.to != StageId::Dedup)
+            .map(|r| match r.from {
+                StageId::Dedup => Route {
+                    from: StageId::Filter,
+                    ..r
+                },
+                _ => r,
+            })
+            .collect()
+    }
+}
+
+// under the block policy a cycle ends up waiting on its own full queue
+fn check_cycles(routes: &[Route]) -> anyhow::Result<()> {
+    fn visit(
+        id: StageId,
+        routes: &[Route],
+        path: &mut Vec<StageId>,
+        done: &mut HashSet<StageId>,
+    ) -> anyhow::Result<()> {
+        if let Some(start) = path.iter().position(|p| *p == id) {
+            let cycle = path[start..]
+                .iter()
+                .chain([&id])
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(" → ");
+            bail!("o pipeline tem um ciclo: {cycle}");
+        }
+        if done.contains(&id) {
+            return Ok(());
+        }
+
+        path.push(id);
+        for route in routes.iter().filter(|r| r.from == id) {
+            visit(route.to, routes, path, done)?;
+        }
+        path.pop();
+        done.insert(id);
+        Ok(())
+    }
+
+    let mut done = HashSet::new();
+    for route in routes {
+        visit(route.from, routes, &mut vec![], &mut done)?;
+    }
+    Ok(())
+}
+
+// the graph the stages used to hardcode
+pub fn default_routes() -> Vec<Route> {
+    let js = Condition {
+        content_type: vec!["javascript".into()],
+        ..Default::default()
+    };
+    let not_js = Condition {
+        not_content_type: vec!["javascript".into()],
+        ..Default::default()
+    };
+
+    vec![
+        Route::new(StageId::Filter, "exchange", StageId::Archive, Condition::default()),
+        Route::new(StageId::Filter, "exchange", StageId::Session, Condition::default()),
+        Route::new(StageId::Filter, OUT, StageId::Dedup, Condition::default()),
+        Route::new(StageId::Dedup, OUT, StageId::Map, js),
+        Route::new(StageId::Dedup, OUT, StageId::Scan, not_js),
+        Route::new(StageId::Map, "sources", StageId::SaveFile, Condition::default()),
+        Route::new(StageId::Map, OUT, StageId::SaveFile, Condition::default()),
+        Route::new(StageId::Map, OUT, StageId::Scan, Condition::default()),
+        Route::new(StageId::Map, OUT, StageId::JsScan, Condition::default()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes(pipeline: &str) -> anyhow::Result<Vec<Route>> {
+        toml::from_str::<PipelineConfig>(pipeline)?.routes()
+    }
+
+    #[test]
+    fn size_limits_apply_to_the_decoded_body() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        std::io::Write::write_all(&mut encoder, &[b'a'; 10_000]).unwrap();
+
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("content-encoding", "gzip".parse().unwrap());
+        let resp = InterceptedResponse::new(
+            "/app.js".into(),
+            "https".into(),
+            "a.test".into(),
+            &headers,
+            encoder.finish().unwrap(),
+            crate::analyzer::intercepted::ConnectionInfo::default(),
+        );
+        assert!(resp.body.len() < 1_000);
+
+        let small = Condition {
+            max_size: Some(1_000),
+            ..Default::default()
+        };
+        let large = Condition {
+            min_size: Some(5_000),
+            ..Default::default()
+        };
+        assert!(!small.matches(&resp));
+        assert!(large.matches(&resp));
+    }
+
+    #[test]
+    fn default_routes_are_valid() {
+        assert_eq!(routes("").unwrap().len(), default_routes().len());
+    }
+
+    #[test]
+    fn disabled_dedup_is_bypassed() {
+        let routes = routes("[stages.dedup]\nenabled = false").unwrap();
+        assert!(routes.iter().all(|r| r.from != StageId::Dedup && r.to != StageId::Dedup));
+        assert!(routes.iter().any(|r| r.from == StageId::Filter && r.to == StageId::Map));
+    }
+
+    #[test]
+    fn rejects_unknown_stages_and_ports() {
+        let err = routes("[[routes]]\nfrom = \"filter\"\nto = \"mapp\"").unwrap_err();
+        assert!(err.to_string().contains("mapp"));
+
+        let err = routes("[[routes]]\nfrom = \"filter\"\nport = \"sources\"\nto = \"map\"").unwrap_err();
+        assert!(err.to_string().contains("sources"));
+
+        assert!(routes("[[routes]]\nfrom = \"scan\"\nto = \"map\"").is_err());
+        assert!(routes("[[routes]]\nfrom = \"map\"\nport = \"sources\"\nto = \"scan\"").is_ok());
+    }
+
+    #[test]
+    fn rejects_routes_to_disabled_stages() {
+        let err = routes(
+            "[stages.js-scan]\nenabled = false\n\n[[routes]]\nfrom = \"map\"\nto = \"js-scan\"",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("desativado"));
+    }
+
+    #[test]
+    fn rejects_cycles() {
+        let err = routes(
+            "[[routes]]\nfrom = \"filter\"\nto = \"dedup\"\n\n\
+             [[routes]]\nfrom = \"dedup\"\nto = \"map\"\n\n\
+             [[routes]]\nfrom = \"map\"\nto = \"dedup\"",
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "o pipeline tem um ciclo: dedup → map → dedup");
+    }
+}
@@ -1,20 +1,74 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{any::Any, collections::HashMap, panic::AssertUnwindSafe, path::PathBuf, sync::Arc, time::Duration};
 
-use tokio::{sync::{broadcast, mpsc::unbounded_channel}, task::JoinHandle};
+use futures::FutureExt;
+use tokio::{
+    sync::{
+        Semaphore, broadcast,
+        mpsc::{Receiver, channel, error::TryRecvError},
+    },
+    task::JoinHandle,
+    time::Instant,
+};
 
 use crate::analyzer::{
-    event::Dispatcher,
+    cpu::CpuLimits,
+    dead_letter::DeadLetters,
+    event::{Dispatcher, Event, Lease, Outbox, QueuePolicy, StageQueue},
+    intercepted::InterceptedResponse,
+    pipeline::{Route, default_routes},
+    sink::{FindingSink, FindingSinks, SinkKind},
+    spill::SpillQueue,
     stage::{Stage, StageId},
 };
 
+pub const DEFAULT_STAGE_WORKERS: usize = 4;
+pub const DEFAULT_MAX_CONCURRENCY: usize = 32;
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1000;
+pub const DEFAULT_STAGE_TIMEOUT: u64 = 120;
+
+const QUEUE_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct DispatchOptions {
+    pub stage_workers: Vec<(StageId, usize)>,
+    pub max_concurrency: usize,
+    pub queue_capacity: usize,
+    pub queue_policy: QueuePolicy,
+    pub spill_dir: PathBuf,
+    pub finding_sinks: Vec<SinkKind>,
+    pub stage_timeout: Duration,
+    pub dead_letter: PathBuf,
+    pub cpu: CpuLimits,
+}
+
 pub struct StageRegistry {
     stages: HashMap<StageId, Box<dyn Stage + Send + Sync>>,
+    routes: Vec<Route>,
+    sinks: Vec<Box<dyn FindingSink>>,
+    workers: HashMap<StageId, usize>,
+    timeouts: HashMap<StageId, Duration>,
+    stage_timeout: Duration,
+    dead_letter: PathBuf,
+    max_concurrency: usize,
+    queue_capacity: usize,
+    queue_policy: QueuePolicy,
+    spill_dir: PathBuf,
 }
 
 impl StageRegistry {
     pub fn new() -> Self {
         Self {
             stages: HashMap::new(),
+            routes: default_routes(),
+            sinks: vec![],
+            workers: HashMap::new(),
+            timeouts: HashMap::new(),
+            stage_timeout: Duration::from_secs(DEFAULT_STAGE_TIMEOUT),
+            dead_letter: PathBuf::from("dead-letter.jsonl"),
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            queue_policy: QueuePolicy::Block,
+            spill_dir: PathBuf::from("spill"),
         }
     }
 
@@ -23,8 +77,67 @@ impl StageRegistry {
         self
     }
 
+    pub fn routes(mut self, routes: Vec<Route>) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    pub fn sink(mut self, sink: Box<dyn FindingSink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn workers(mut self, id: StageId, workers: usize) -> Self {
+        self.workers.insert(id, workers.max(1));
+        self
+    }
+
+    pub fn timeout(mut self, id: StageId, timeout: Duration) -> Self {
+        self.timeouts.insert(id, timeout);
+        self
+    }
+
+    pub fn stage_timeout(mut self, timeout: Duration) -> Self {
+        self.stage_timeout = timeout;
+        self
+    }
+
+    pub fn dead_letter(mut self, path: PathBuf) -> Self {
+        self.dead_letter = path;
+        self
+    }
+
+    pub fn max_concurrency(mut self, max: usize) -> Self {
+        self.max_concurrency = max.max(1);
+        self
+    }
+
+    pub fn queue(mut self, capacity: usize, policy: QueuePolicy, spill_dir: PathBuf) -> Self {
+        self.queue_capacity = capacity.max(1);
+        self.queue_policy = policy;
+        self.spill_dir = spill_dir;
+        self
+    }
+
+    pub fn options(self, options: &DispatchOptions) -> Self {
+        let registry = options
+            .stage_workers
+            .iter()
+            .fold(self, |registry, (id, workers)| registry.workers(*id, *workers));
+
+        registry
+            .max_concurrency(options.max_concurrency)
+            .queue(
+                options.queue_capacity,
+                options.queue_policy,
+                options.spill_dir.clone(),
+            )
+            .stage_timeout(options.stage_timeout)
+            .dead_letter(options.dead_letter.clone())
+    }
+
     pub fn build(self) -> (Dispatcher, JoinHandle<()>) {
-        start_stage_router(self.stages)
+        start_stage_router(self)
     }
 }
 
@@ -34,25 +147,82 @@ impl Default for StageRegistry {
     }
 }
 
-fn start_stage_router(
-    stages: HashMap<StageId, Box<dyn Stage + Send + Sync>>,
-) -> (Dispatcher, JoinHandle<()>) {
-    let (tx, mut rx) = unbounded_channel();
+fn start_stage_router(registry: StageRegistry) -> (Dispatcher, JoinHandle<()>) {
     let (closed, mut closed_rx) = broadcast::channel(1);
-    let dispatcher = Dispatcher::new(tx, closed);
 
-    let stages = Arc::new(stages);
-    let tk_stages = stages.clone();
+    let global = Arc::new(Semaphore::new(registry.max_concurrency));
+    let dead_letters = Arc::new(DeadLetters::new(registry.dead_letter));
+    let mut queues = HashMap::new();
+    let mut runners = vec![];
+
+    for (id, stage) in registry.stages {
+        let workers = registry
+            .workers
+            .get(&id)
+            .copied()
+            .unwrap_or(DEFAULT_STAGE_WORKERS);
+        let timeout = registry
+            .timeouts
+            .get(&id)
+            .copied()
+            .unwrap_or(registry.stage_timeout);
+
+        let (tx, rx) = channel(registry.queue_capacity);
+        let spill = match registry.queue_policy {
+            QueuePolicy::Spill => Some(Arc::new(SpillQueue::new(
+                registry.spill_dir.join(format!("{id}.jsonl")),
+            ))),
+            _ => None,
+        };
+
+        queues.insert(
+            id,
+            StageQueue {
+                tx,
+                spill: spill.clone(),
+                dropped: Default::default(),
+            },
+        );
+        runners.push(StageRunner {
+            id,
+            stage: Arc::from(stage),
+            timeout,
+            workers: Arc::new(Semaphore::new(workers)),
+            pending: Arc::new(Semaphore::new(workers * 2)),
+            outbox_limit: registry.queue_capacity,
+            dead_letters: dead_letters.clone(),
+            rx,
+            spill,
+        });
+    }
+
+    let dispatcher = Dispatcher::new(
+        queues,
+        registry.routes,
+        FindingSinks::new(registry.sinks),
+        registry.queue_policy,
+        closed,
+    );
+
+    for runner in runners {
+        tokio::spawn(run_stage(runner, dispatcher.clone(), global.clone()));
+    }
+
     let tk_dispatcher = dispatcher.clone();
-    
+
     let handle = tokio::spawn(async move {
+        let mut report = tokio::time::interval(QUEUE_REPORT_INTERVAL);
+        let mut last = vec![];
+
         loop {
             tokio::select! {
-                Some(event) = rx.recv() => {
-                    if let Some(stage) = tk_stages.get(&event.stage) {
-                        stage.process(tk_dispatcher.clone(), event.resp).await;
+                _ = report.tick() => {
+                    let depths = tk_dispatcher.queue_depths();
+                    let busy = depths.iter().any(|(_, depth, dropped)| *depth > 0 || *dropped > 0);
+                    if busy && depths != last {
+                        println!("📊 [Queue] {}", render_depths(&depths));
                     }
-                    tk_dispatcher.complete();
+                    last = depths;
                 }
 
                 _ = closed_rx.recv() => {
@@ -64,3 +234,292 @@ fn start_stage_router(
 
     (dispatcher, handle)
 }
+
+fn render_depths(depths: &[(StageId, usize, u64)]) -> String {
+    depths
+        .iter()
+        .map(|(id, depth, dropped)| match dropped {
+            0 => format!("{id}={depth}"),
+            _ => format!("{id}={depth} (descartados={dropped})"),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+struct StageRunner {
+    id: StageId,
+    stage: Arc<dyn Stage + Send + Sync>,
+    timeout: Duration,
+    workers: Arc<Semaphore>,
+    // events taken off the queue and not yet forwarded, so a stage stuck on a full downstream queue stops pulling
+    pending: Arc<Semaphore>,
+    outbox_limit: usize,
+    dead_letters: Arc<DeadLetters>,
+    rx: Receiver<Event>,
+    spill: Option<Arc<SpillQueue>>,
+}
+
+// a slow stage only holds back its own queue, never the events of the others
+async fn run_stage(mut runner: StageRunner, dispatcher: Dispatcher, global: Arc<Semaphore>) {
+    loop {
+        let Ok(pending) = runner.pending.clone().acquire_owned().await else {
+            break;
+        };
+        let Some(next) = next_event(&mut runner.rx, runner.spill.as_deref()).await else {
+            break;
+        };
+
+        let resp = match next {
+            Ok(resp) => resp,
+            Err(e) => {
+                eprintln!("❌ [Queue] Evento perdido na fila em disco: {e}");
+                dispatcher.complete();
+                continue;
+            }
+        };
+
+        let Ok(worker) = runner.workers.clone().acquire_owned().await else {
+            break;
+        };
+        let Ok(slot) = global.clone().acquire_owned().await else {
+            break;
+        };
+
+        let id = runner.id;
+        let stage = runner.stage.clone();
+        let timeout = runner.timeout;
+        let dead_letters = runner.dead_letters.clone();
+        let dispatcher = dispatcher.clone();
+        let outbox = Outbox::new(
+            runner.outbox_limit,
+            Lease::new(runner.workers.clone(), global.clone(), (worker, slot)),
+        );
+
+        tokio::spawn(async move {
+            let result = supervise(stage, dispatcher.buffered(outbox.clone()), resp.clone(), timeout).await;
+
+            // a producer waiting on a full queue must not hold the slot its consumer needs
+            outbox.release().await;
+            match result {
+                Ok(()) => {
+                    for event in outbox.take() {
+                        dispatcher.emit(event.stage, event.resp).await;
+                    }
+                }
+                // partial output is dropped, replaying the dead letter produces it again
+                Err(error) => {
+                    resp.fail_analysis();
+                    dead_letters.record(id, resp, &error)
+                }
+            }
+            dispatcher.complete();
+            drop(pending);
+        });
+    }
+}
+
+// a panic or a hung event must never leak its inflight slot; the time spent forwarding an
+// overflowing outbox is added back, so the timeout never covers a wait on a downstream queue
+async fn supervise(
+    stage: Arc<dyn Stage + Send + Sync>,
+    dispatcher: Dispatcher,
+    resp: InterceptedResponse,
+    timeout: Duration,
+) -> Result<(), String> {
+    let outbox = dispatcher.outbox();
+    let started = Instant::now();
+    let mut run = AssertUnwindSafe(stage.process(dispatcher, resp)).catch_unwind();
+
+    let result = loop {
+        let paused = outbox.as_ref().map_or(Duration::ZERO, Outbox::paused);
+        match tokio::time::timeout_at(started + timeout + paused, &mut run).await {
+            Ok(result) => break result,
+            Err(_) if outbox.as_ref().is_some_and(|o| o.paused() > paused) => continue,
+            Err(_) => return Err(format!("timeout após {}s", timeout.as_secs())),
+        }
+    };
+
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(format!("{e:#}")),
+        Err(panic) => Err(format!("panic: {}", panic_message(panic.as_ref()))),
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    if let Some(message) = panic.downcast_ref::<String>() {
+        return message.clone();
+    }
+    "sem mensagem".to_string()
+}
+
+// memory first, then whatever overflowed to disk
+async fn next_event(
+    rx: &mut Receiver<Event>,
+    spill: Option<&SpillQueue>,
+) -> Option<anyhow::Result<InterceptedResponse>> {
+    loop {
+        match rx.try_recv() {
+            Ok(event) => return Some(Ok(event.resp)),
+            Err(TryRecvError::Disconnected) => return None,
+            Err(TryRecvError::Empty) => {}
+        }
+
+        if let Some(spilled) = spill.and_then(|s| s.pop()) {
+            return Some(spilled);
+        }
+
+        let Some(spill) = spill else {
+            return rx.recv().await.map(|event| Ok(event.resp));
+        };
+
+        tokio::select! {
+            event = rx.recv() => return event.map(|event| Ok(event.resp)),
+            _ = spill.notified() => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use async_trait::async_trait;
+    use hyper::HeaderMap;
+
+    use super::*;
+    use crate::analyzer::intercepted::ConnectionInfo;
+
+    struct Fan(usize);
+
+    #[async_trait]
+    impl Stage for Fan {
+        async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
+            for _ in 0..self.0 {
+                dispatcher.emit(StageId::Scan, resp.clone()).await;
+            }
+            Ok(())
+        }
+    }
+
+    struct Count(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Stage for Count {
+        async fn process(&self, _: Dispatcher, _: InterceptedResponse) -> anyhow::Result<()> {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    struct Failing;
+
+    #[async_trait]
+    impl Stage for Failing {
+        async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
+            dispatcher.emit(StageId::Scan, resp).await;
+            anyhow::bail!("falhou depois de emitir")
+        }
+    }
+
+    fn response(path: String) -> InterceptedResponse {
+        InterceptedResponse::new(
+            path,
+            "http".into(),
+            "example.com".into(),
+            &HeaderMap::new(),
+            Vec::new(),
+            ConnectionInfo::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn failed_events_do_not_forward_partial_output() {
+        let seen = Arc::new(AtomicUsize::new(0));
+        let dead_letter = std::env::temp_dir().join(format!("mboitata-dead-{}.jsonl", std::process::id()));
+        let (dispatcher, _) = StageRegistry::new()
+            .register(StageId::Filter, Box::new(Failing))
+            .register(StageId::Scan, Box::new(Count(seen.clone())))
+            .routes(vec![])
+            .dead_letter(dead_letter.clone())
+            .build();
+
+        dispatcher.emit(StageId::Filter, response("/app.js".into())).await;
+        tokio::time::timeout(Duration::from_secs(10), dispatcher.close_gracefully())
+            .await
+            .unwrap();
+
+        assert_eq!(seen.load(Ordering::SeqCst), 0);
+        assert_eq!(std::fs::read_to_string(&dead_letter).unwrap().lines().count(), 1);
+        std::fs::remove_file(dead_letter).unwrap();
+    }
+
+    struct Waits(usize, Arc<AtomicUsize>);
+
+    // emits more than the queue holds and only returns once the consumer has seen them
+    #[async_trait]
+    impl Stage for Waits {
+        async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
+            for _ in 0..self.0 {
+                dispatcher.emit(StageId::Scan, resp.clone()).await;
+            }
+            while self.1.load(Ordering::SeqCst) < self.0 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn an_outbox_past_the_queue_capacity_is_forwarded_before_the_stage_returns() {
+        let seen = Arc::new(AtomicUsize::new(0));
+        let (dispatcher, _) = StageRegistry::new()
+            .register(StageId::Filter, Box::new(Waits(10, seen.clone())))
+            .register(StageId::Scan, Box::new(Count(seen.clone())))
+            .routes(vec![])
+            .max_concurrency(1)
+            .stage_timeout(Duration::from_secs(1))
+            .queue(2, QueuePolicy::Block, std::env::temp_dir().join("mboitata-router-spill"))
+            .build();
+
+        let feed = async {
+            dispatcher.emit(StageId::Filter, response("/app.js".into())).await;
+            dispatcher.close_gracefully().await;
+        };
+
+        tokio::time::timeout(Duration::from_secs(10), feed)
+            .await
+            .expect("saída retida até o fim do stage");
+        assert_eq!(seen.load(Ordering::SeqCst), 10);
+    }
+
+    #[tokio::test]
+    async fn producers_blocked_on_a_full_queue_do_not_starve_the_consumer() {
+        let seen = Arc::new(AtomicUsize::new(0));
+        let (dispatcher, _) = StageRegistry::new()
+            .register(StageId::Filter, Box::new(Fan(3)))
+            .register(StageId::Scan, Box::new(Count(seen.clone())))
+            .routes(vec![])
+            .workers(StageId::Filter, 4)
+            .workers(StageId::Scan, 1)
+            .max_concurrency(2)
+            .queue(1, QueuePolicy::Block, std::env::temp_dir().join("mboitata-router-spill"))
+            .build();
+
+        let feed = async {
+            for i in 0..50 {
+                dispatcher.emit(StageId::Filter, response(format!("/{i}.js"))).await;
+            }
+            dispatcher.close_gracefully().await;
+        };
+
+        tokio::time::timeout(Duration::from_secs(10), feed)
+            .await
+            .expect("pipeline travou com a fila cheia");
+        assert_eq!(seen.load(Ordering::SeqCst), 150);
+    }
+}
@@ -5,22 +5,85 @@ use tokio::task::JoinHandle;
 use crate::{
     allow_list::AllowList,
     analyzer::{
+        content_store::{BODIES_DIR, ContentStore},
+        cpu::CpuPool,
         event::Dispatcher,
-        router::StageRegistry,
-        stage::StageId,
+        pipeline::PipelineConfig,
+        router::{DispatchOptions, StageRegistry},
+        stage::{Stage, StageId},
         stages::{
-            filter_stage::FilterStage, map_stage::MapStage, save_file_stage::SaveFileStage,
-            scan_js_stage::ScanJsStage, scan_stage::ScanStage,
+            archive_stage::ArchiveStage, dedup_stage::DedupStage, filter_stage::FilterStage, map_stage::MapStage, save_file_stage::SaveFileStage,
+            scan_js_stage::ScanJsStage, scan_stage::ScanStage, session_stage::SessionStage,
         },
     },
+    host_map::HostMap,
+    session::Session,
 };
 
-pub fn initialize_stages(allow_list: AllowList, output: PathBuf) -> (Dispatcher, JoinHandle<()>) {
-    StageRegistry::default()
-        .register(StageId::Filter, Box::new(FilterStage::new(allow_list)))
-        .register(StageId::Map, Box::new(MapStage::new()))
-        .register(StageId::SaveFile, Box::new(SaveFileStage::new(output)))
-        .register(StageId::Scan, Box::new(ScanStage::new()))
-        .register(StageId::JsScan, Box::new(ScanJsStage::new()))
-        .build()
+pub fn initialize_stages(
+    allow_list: AllowList,
+    output: PathBuf,
+    host_map: HostMap,
+    options: &DispatchOptions,
+    pipeline: &PipelineConfig,
+    session: Option<Session>,
+) -> anyhow::Result<(Dispatcher, JoinHandle<()>)> {
+    let pool = CpuPool::new(options.cpu);
+    let content = match &session {
+        Some(session) => ContentStore::with_session(session.clone())?,
+        None => ContentStore::open(output.join(BODIES_DIR))?,
+    };
+    let mut stages: Vec<(StageId, Box<dyn Stage + Send + Sync>)> = vec![
+        (StageId::Filter, Box::new(FilterStage::new(allow_list, pipeline.options(StageId::Filter)?))),
+        (StageId::Dedup, Box::new(DedupStage::new(content))),
+        (StageId::Archive, Box::new(ArchiveStage::new(output.clone()))),
+        (StageId::Map, Box::new(MapStage::new(host_map, pipeline.options(StageId::Map)?, pool.clone()))),
+        (StageId::SaveFile, Box::new(SaveFileStage::new(output.clone(), pipeline.options(StageId::SaveFile)?))),
+        (StageId::Scan, Box::new(ScanStage::new(pool.clone()))),
+        (StageId::JsScan, Box::new(ScanJsStage::new(pool))),
+    ];
+
+    if let Some(session) = &session {
+        stages.push((StageId::Session, Box::new(SessionStage::new(session.clone()))));
+    }
+
+    for (id, plugin) in pipeline.plugins() {
+        if pipeline.is_enabled(id) {
+            stages.push((id, plugin.stage(id)?));
+        }
+    }
+
+    // workers from the pipeline file first, so --stage-workers still wins
+    let registry = pipeline
+        .stage_workers()
+        .into_iter()
+        .fold(StageRegistry::default(), |registry, (id, workers)| registry.workers(id, workers))
+        .options(options);
+
+    let registry = pipeline
+        .stage_timeouts()
+        .into_iter()
+        .fold(registry, |registry, (id, timeout)| registry.timeout(id, timeout))
+        .routes(pipeline.routes()?);
+
+    let registry = options
+        .finding_sinks
+        .iter()
+        .fold(registry, |registry, kind| registry.sink(kind.build(&output)));
+
+    let registry = match session {
+        Some(session) => registry.sink(Box::new(session)),
+        None => registry,
+    };
+
+    let registry = stages.into_iter().fold(registry, |registry, (id, stage)| {
+        if pipeline.is_enabled(id) {
+            registry.register(id, stage)
+        } else {
+            println!("[Pipeline] Stage {id} desativado.");
+            registry
+        }
+    });
+
+    Ok(registry.build())
 }
@@ -0,0 +1,146 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use clap::ValueEnum;
+use lru::LruCache;
+use sanitize_filename::sanitize;
+
+use crate::analyzer::finding::{Finding, Position};
+
+pub const FINDINGS_DIR: &str = "findings";
+pub const FINDINGS_FILE: &str = "findings.jsonl";
+const RECENT_FINDINGS: usize = 10_000;
+
+pub trait FindingSink: Send + Sync {
+    fn report(&self, finding: &Finding) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SinkKind {
+    Text,
+    Json,
+}
+
+impl SinkKind {
+    pub fn build(self, output: &Path) -> Box<dyn FindingSink> {
+        match self {
+            SinkKind::Text => Box::new(TextSink::new(output.join(FINDINGS_DIR))),
+            SinkKind::Json => Box::new(JsonSink::new(output.join(FINDINGS_FILE))),
+        }
+    }
+}
+
+fn append_line(path: &Path, line: &str) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{line}")?;
+    Ok(())
+}
+
+// one file per host and kind, so findings from same-named files never overwrite each other
+pub struct TextSink {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl TextSink {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl FindingSink for TextSink {
+    fn report(&self, finding: &Finding) -> anyhow::Result<()> {
+        let path = self
+            .dir
+            .join(sanitize(&finding.host))
+            .join(format!("{}.txt", sanitize(&finding.kind)));
+
+        let mut line = format!(
+            "{}\t{}\t{:?}",
+            finding.value,
+            finding.location(),
+            finding.confidence
+        );
+        let detail = finding.detail.as_deref().map(|d| d.replace('\n', " "));
+        // the trail only says more than the location when something derived the source
+        if finding.trail.len() > 1 {
+            line.push_str(&format!("\t{}\t{}", detail.as_deref().unwrap_or("-"), finding.explain()));
+        } else if let Some(detail) = detail {
+            line.push_str(&format!("\t{detail}"));
+        }
+
+        let _guard = self.lock.lock().unwrap();
+        append_line(&path, &line)
+    }
+}
+
+pub struct JsonSink {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JsonSink {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl FindingSink for JsonSink {
+    fn report(&self, finding: &Finding) -> anyhow::Result<()> {
+        let line = serde_json::to_string(finding)?;
+
+        let _guard = self.lock.lock().unwrap();
+        append_line(&self.path, &line)
+    }
+}
+
+type FindingKey = (String, String, String, Option<Position>);
+
+// only recent findings are remembered, enough to catch a body scanned twice in a row without
+// growing for the whole crawl; the session database keeps findings unique for good
+pub struct FindingSinks {
+    sinks: Vec<Box<dyn FindingSink>>,
+    seen: Mutex<LruCache<FindingKey, ()>>,
+}
+
+impl FindingSinks {
+    pub fn new(sinks: Vec<Box<dyn FindingSink>>) -> Self {
+        Self {
+            sinks,
+            seen: Mutex::new(LruCache::new(NonZeroUsize::new(RECENT_FINDINGS).unwrap())),
+        }
+    }
+
+    pub fn report(&self, finding: Finding) {
+        let key = (
+            finding.kind.clone(),
+            finding.value.clone(),
+            finding.source.clone(),
+            finding.position,
+        );
+        if self.seen.lock().unwrap().put(key, ()).is_some() {
+            return;
+        }
+
+        for sink in &self.sinks {
+            if let Err(e) = sink.report(&finding) {
+                eprintln!("❌ [Findings] Erro ao registrar {} de {}: {}", finding.kind, finding.source, e);
+            }
+        }
+    }
+}
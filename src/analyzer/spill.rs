@@ -0,0 +1,110 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use tokio::sync::Notify;
+
+use crate::analyzer::intercepted::InterceptedResponse;
+
+#[derive(Default)]
+struct SpillState {
+    writer: Option<File>,
+    reader: Option<BufReader<File>>,
+    pending: usize,
+}
+
+pub struct SpillQueue {
+    path: PathBuf,
+    state: Mutex<SpillState>,
+    notify: Notify,
+}
+
+impl SpillQueue {
+    pub fn new(path: PathBuf) -> Self {
+        // leftovers from a previous session were never counted as inflight here
+        let _ = fs::remove_file(&path);
+
+        Self {
+            path,
+            state: Mutex::new(SpillState::default()),
+            notify: Notify::new(),
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().pending
+    }
+
+    pub fn push(&self, resp: &InterceptedResponse) -> anyhow::Result<()> {
+        let line = serde_json::to_string(resp)?;
+
+        let mut state = self.state.lock().unwrap();
+        if state.writer.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let writer = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            let reader = BufReader::new(File::open(&self.path)?);
+
+            state.writer = Some(writer);
+            state.reader = Some(reader);
+        }
+
+        if let Some(writer) = state.writer.as_mut() {
+            writeln!(writer, "{line}")?;
+            writer.flush()?;
+        }
+        state.pending += 1;
+        drop(state);
+
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    pub fn pop(&self) -> Option<anyhow::Result<InterceptedResponse>> {
+        let mut state = self.state.lock().unwrap();
+        if state.pending == 0 {
+            return None;
+        }
+
+        let mut line = String::new();
+        let read = state.reader.as_mut()?.read_line(&mut line);
+        state.pending -= 1;
+
+        if state.pending == 0 {
+            self.reset(&mut state);
+        }
+
+        Some(match read {
+            Ok(0) => Err(anyhow::anyhow!(
+                "fila em disco {:?} terminou antes do esperado",
+                self.path
+            )),
+            Ok(_) => serde_json::from_str(&line).map_err(Into::into),
+            Err(e) => Err(e.into()),
+        })
+    }
+
+    pub async fn notified(&self) {
+        self.notify.notified().await;
+    }
+
+    fn reset(&self, state: &mut SpillState) {
+        let truncated = match (state.writer.as_mut(), state.reader.as_mut()) {
+            (Some(writer), Some(reader)) => writer
+                .set_len(0)
+                .and_then(|_| reader.seek(SeekFrom::Start(0)).map(|_| ())),
+            _ => Ok(()),
+        };
+
+        if let Err(e) = truncated {
+            eprintln!("Erro ao truncar a fila em disco {:?}: {}", self.path, e);
+        }
+    }
+}
@@ -1,17 +1,85 @@
+use std::{fmt, str::FromStr, sync::Mutex};
+
 use async_trait::async_trait;
 
-use crate::analyzer::{event::Dispatcher, intercepted::InterceptedResponse};
+use crate::analyzer::{event::Dispatcher, intercepted::InterceptedResponse, pipeline::OUT};
 
 #[async_trait]
 pub trait Stage: Send + Sync {
-    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse);
+    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()>;
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum StageId {
     Filter,
+    Dedup,
+    Archive,
     Map,
     SaveFile,
     Scan,
-    JsScan
+    JsScan,
+    Session,
+    Plugin(&'static str),
+}
+
+impl StageId {
+    // ids stay Copy, so plugin names are interned once instead of owned
+    pub fn plugin(name: &str) -> Self {
+        static NAMES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+        let mut names = NAMES.lock().unwrap();
+        if let Some(interned) = names.iter().find(|n| **n == name) {
+            return StageId::Plugin(interned);
+        }
+
+        let interned: &'static str = Box::leak(name.to_string().into_boxed_str());
+        names.push(interned);
+        StageId::Plugin(interned)
+    }
+
+    // the ports a built-in stage forwards on; plugins pick their own, so they aren't checked
+    pub fn ports(&self) -> Option<&'static [&'static str]> {
+        match self {
+            StageId::Filter => Some(&["exchange", OUT]),
+            StageId::Dedup => Some(&[OUT]),
+            StageId::Map => Some(&["sources", OUT]),
+            StageId::Plugin(_) => None,
+            _ => Some(&[]),
+        }
+    }
+}
+
+impl FromStr for StageId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "filter" => Ok(StageId::Filter),
+            "dedup" => Ok(StageId::Dedup),
+            "archive" => Ok(StageId::Archive),
+            "map" => Ok(StageId::Map),
+            "save-file" | "savefile" => Ok(StageId::SaveFile),
+            "scan" => Ok(StageId::Scan),
+            "js-scan" | "jsscan" => Ok(StageId::JsScan),
+            "session" => Ok(StageId::Session),
+            other => anyhow::bail!("stage desconhecido: {other}"),
+        }
+    }
+}
+
+impl fmt::Display for StageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            StageId::Filter => "filter",
+            StageId::Dedup => "dedup",
+            StageId::Archive => "archive",
+            StageId::Map => "map",
+            StageId::SaveFile => "save-file",
+            StageId::Scan => "scan",
+            StageId::JsScan => "js-scan",
+            StageId::Session => "session",
+            StageId::Plugin(name) => name,
+        };
+        f.write_str(name)
+    }
 }
@@ -0,0 +1,154 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::{
+    event::Dispatcher,
+    intercepted::{ExchangeRecord, InterceptedResponse, base64_bytes},
+    stage::Stage,
+};
+
+pub const ARCHIVE_FILE: &str = "exchanges.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub id: u64,
+    #[serde(flatten)]
+    pub exchange: ExchangeRecord,
+    #[serde(with = "base64_bytes")]
+    pub response_body: Vec<u8>,
+}
+
+pub struct ArchiveStage {
+    path: PathBuf,
+    next_id: Mutex<u64>,
+}
+
+impl ArchiveStage {
+    pub fn new(output_dir: PathBuf) -> Self {
+        let path = output_dir.join(ARCHIVE_FILE);
+        let next_id = load_archive(&path)
+            .ok()
+            .and_then(|entries| entries.iter().map(|e| e.id).max())
+            .map_or(1, |id| id + 1);
+        close_truncated_line(&path);
+
+        Self {
+            path,
+            next_id: Mutex::new(next_id),
+        }
+    }
+}
+
+#[async_trait]
+impl Stage for ArchiveStage {
+    async fn process(&self, _: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
+        let Some(exchange) = resp.exchange.as_deref() else {
+            return Ok(());
+        };
+
+        let mut next_id = self.next_id.lock().unwrap();
+        let entry = ArchiveEntry {
+            id: *next_id,
+            exchange: exchange.clone(),
+            response_body: resp.body.raw().to_vec(),
+        };
+
+        let line = serde_json::to_string(&entry)
+            .map_err(|e| anyhow!("erro ao serializar exchange {}: {}", exchange.url, e))?;
+
+        let parent = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(parent)
+            .map_err(|e| anyhow!("erro ao criar diretório {:?}: {}", parent, e))?;
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{line}"))
+            .map_err(|e| anyhow!("erro ao salvar o arquivo {:?}: {}", self.path, e))?;
+
+        *next_id += 1;
+        Ok(())
+    }
+}
+
+pub fn load_archive(path: &Path) -> anyhow::Result<Vec<ArchiveEntry>> {
+    let file = fs::File::open(path)?;
+    let mut entries = vec![];
+
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        // a kill mid-write leaves a truncated line behind, it shouldn't hide the rest of the archive
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => eprintln!("⚠️ Ignorando linha {} inválida de {:?}: {}", number + 1, path, e),
+        }
+    }
+
+    Ok(entries)
+}
+
+// without this the next entry would be appended to the end of a truncated line
+fn close_truncated_line(path: &Path) {
+    let ends_open = fs::read(path).is_ok_and(|content| content.last().is_some_and(|b| *b != b'\n'));
+    if !ends_open {
+        return;
+    }
+
+    if let Err(e) = OpenOptions::new().append(true).open(path).and_then(|mut file| writeln!(file)) {
+        eprintln!("❌ Erro ao corrigir o arquivo {:?}: {}", path, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_truncated_lines_and_keeps_the_highest_id() {
+        let dir = std::env::temp_dir().join(format!("mboitata-archive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(ARCHIVE_FILE);
+
+        let entry = |id| {
+            serde_json::to_string(&ArchiveEntry {
+                id,
+                exchange: ExchangeRecord {
+                    method: "GET".into(),
+                    url: format!("http://example.com/{id}"),
+                    request_headers: vec![],
+                    request_body: vec![],
+                    status: 200,
+                    response_headers: vec![],
+                },
+                response_body: vec![],
+            })
+            .unwrap()
+        };
+        let truncated = entry(9);
+        fs::write(
+            &path,
+            format!("{}\n{}\n{}", entry(1), entry(7), &truncated[..truncated.len() / 2]),
+        )
+        .unwrap();
+
+        assert_eq!(load_archive(&path).unwrap().len(), 2);
+
+        let stage = ArchiveStage::new(dir.clone());
+        assert_eq!(*stage.next_id.lock().unwrap(), 8);
+        assert!(fs::read_to_string(&path).unwrap().ends_with('\n'));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
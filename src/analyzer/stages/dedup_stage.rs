@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::analyzer::{
+    content_store::{Analysis, ContentStore},
+    event::Dispatcher,
+    intercepted::InterceptedResponse,
+    pipeline::OUT,
+    stage::{Stage, StageId},
+};
+
+pub struct DedupStage {
+    store: Arc<ContentStore>,
+}
+
+impl DedupStage {
+    pub fn new(store: ContentStore) -> Self {
+        Self { store: Arc::new(store) }
+    }
+}
+
+#[async_trait]
+impl Stage for DedupStage {
+    async fn process(&self, dispatcher: Dispatcher, mut resp: InterceptedResponse) -> anyhow::Result<()> {
+        let Some(body) = resp.decoded_body() else {
+            dispatcher.forward(StageId::Dedup, OUT, resp).await;
+            return Ok(());
+        };
+
+        let url = resp.location();
+        let stored = self.store.store(&url, &resp.content_type, body)?;
+
+        if stored.new_content {
+            resp.analysis = Some(Arc::new(Analysis::new(self.store.clone(), stored.hash)));
+            dispatcher.forward(StageId::Dedup, OUT, resp).await;
+        } else if stored.new_url {
+            println!("[Dedup] {} tem o mesmo conteúdo de {} já analisado.", url, &stored.hash[..12]);
+        } else {
+            println!("[Dedup] Ignorando {} (já analisado).", url);
+        }
+        Ok(())
+    }
+}
@@ -1,44 +1,77 @@
 use async_trait::async_trait;
+use serde::Deserialize;
 
 use crate::{
     allow_list::AllowList,
     analyzer::{
         event::Dispatcher,
         intercepted::InterceptedResponse,
+        pipeline::OUT,
         stage::{Stage, StageId},
     },
 };
 
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterOptions {
+    pub skip_content_types: Vec<String>,
+    pub skip_extensions: Vec<String>,
+}
+
+impl Default for FilterOptions {
+    fn default() -> Self {
+        Self {
+            skip_content_types: vec!["image/".into()],
+            skip_extensions: vec!["css".into()],
+        }
+    }
+}
+
 pub struct FilterStage {
     allow_list: AllowList,
+    options: FilterOptions,
 }
 
 impl FilterStage {
-    pub fn new(allow_list: AllowList) -> Self {
-        Self { allow_list }
+    pub fn new(allow_list: AllowList, options: FilterOptions) -> Self {
+        Self {
+            allow_list,
+            options,
+        }
+    }
+
+    fn skipped(&self, resp: &InterceptedResponse) -> bool {
+        self.options
+            .skip_content_types
+            .iter()
+            .any(|t| resp.content_type.starts_with(t.as_str()))
+            || self
+                .options
+                .skip_extensions
+                .iter()
+                .any(|ext| resp.path.ends_with(ext.as_str()))
     }
 }
 
 #[async_trait]
 impl Stage for FilterStage {
-    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) {
+    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
         if !self.allow_list.in_scope(&resp.host) {
             println!("[Filter] Fora do escopo: {}", resp.host);
-            return;
+            return Ok(());
         }
 
-        if resp.content_type.starts_with("image/") || resp.path.ends_with("css") {
-            println!("[Filter] Ignorando image/css: {}", resp.path);
-            return;
+        if resp.exchange.is_some() {
+            dispatcher.forward(StageId::Filter, "exchange", resp.clone()).await;
         }
 
-        if resp.content_type.contains("javascript") {
-            println!("[Filter] JS/MAP detectado: {}", resp.path);
-            dispatcher.emit(StageId::Map, resp.clone());
-            return;
+        if self.skipped(&resp) {
+            println!("[Filter] Ignorando {}: {}", resp.content_type, resp.path);
+            return Ok(());
         }
 
-        println!("[Filter] Conteúdo geral: {}", resp.path);
-        dispatcher.emit(StageId::Scan, resp.clone());
+        println!("[Filter] Em escopo: {} [{}] ({})", resp.path, resp.content_type, resp.conn);
+        dispatcher.forward(StageId::Filter, OUT, resp).await;
+        Ok(())
     }
 }
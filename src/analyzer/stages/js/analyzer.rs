@@ -1,19 +1,22 @@
 use std::collections::HashMap;
 
 use swc_common::{FileName, SourceMap, sync::Lrc};
+use swc_ecma_ast::Module;
 use swc_ecma_parser::{EsSyntax, Parser, StringInput, Syntax};
 use swc_ecma_visit::VisitWith;
 
 use crate::analyzer::stages::js::{JsResult, extractor::VarExtractor, usage::JsUsageAnalyzer};
 
-pub fn run_js_analysis(filename: &str, js_code: &str) -> anyhow::Result<JsResult> {
+pub type JsAst = Module;
+
+pub fn parse_module(filename: &str, js_code: &str) -> anyhow::Result<JsAst> {
     let cm: Lrc<SourceMap> = Default::default();
     let fm = cm.new_source_file(
         FileName::Custom(filename.to_string()).into(),
         js_code.to_string(),
     );
 
-    let lexer = Parser::new(
+    let mut parser = Parser::new(
         Syntax::Es(EsSyntax {
             jsx: false,
             ..Default::default()
@@ -22,12 +25,12 @@ pub fn run_js_analysis(filename: &str, js_code: &str) -> anyhow::Result<JsResult
         None,
     );
 
-    let mut parser = lexer;
-    let module = match parser.parse_module() {
-        Ok(module) => module,
-        Err(err) => return Err(anyhow::anyhow!("Erro ao fazer parse: {:?}", err)),
-    };
+    parser
+        .parse_module()
+        .map_err(|err| anyhow::anyhow!("Erro ao fazer parse: {:?}", err))
+}
 
+pub fn analyze_module(module: &JsAst) -> JsResult {
     let mut var_extractor = VarExtractor {
         vars: HashMap::new(),
         stack: Vec::new()
@@ -39,5 +42,9 @@ pub fn run_js_analysis(filename: &str, js_code: &str) -> anyhow::Result<JsResult
     };
     module.visit_with(&mut usage_analyzer);
 
-    Ok(usage_analyzer.result)
+    usage_analyzer.result
+}
+
+pub fn run_js_analysis(filename: &str, js_code: &str) -> anyhow::Result<JsResult> {
+    Ok(analyze_module(&parse_module(filename, js_code)?))
 }
@@ -1,77 +1,134 @@
+use std::sync::{Arc, LazyLock};
+
 use async_trait::async_trait;
 use hyper::StatusCode;
 use regex::Regex;
 use reqwest::Client;
+use serde::Deserialize;
 use sourcemap::{DecodedMap, decode_slice};
 
-use crate::analyzer::{
-    event::Dispatcher,
-    intercepted::InterceptedResponse,
-    stage::{Stage, StageId},
+use crate::{
+    analyzer::{
+        cpu::CpuPool,
+        event::Dispatcher,
+        intercepted::InterceptedResponse,
+        pipeline::OUT,
+        stage::{Stage, StageId},
+    },
+    host_map::HostMap,
 };
 
-pub struct MapStage;
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MapOptions {
+    pub user_agent: String,
+    pub fallback: bool,
+}
+
+impl Default for MapOptions {
+    fn default() -> Self {
+        Self {
+            user_agent: "mboi-tata/0.1".into(),
+            fallback: true,
+        }
+    }
+}
+
+static SOURCE_MAPPING_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)//[#@]\s*sourceMappingURL\s*=\s*(?P<map>[^\s]+)").unwrap());
+
+pub struct MapStage {
+    host_map: HostMap,
+    options: MapOptions,
+    pool: CpuPool,
+}
 
 impl MapStage {
-    pub fn new() -> Self {
-        Self
+    pub fn new(host_map: HostMap, options: MapOptions, pool: CpuPool) -> Self {
+        Self {
+            host_map,
+            options,
+            pool,
+        }
     }
 }
 
+fn map_references(body: &str) -> Vec<String> {
+    SOURCE_MAPPING_URL
+        .captures_iter(body)
+        .filter_map(|caps| caps.name("map"))
+        .map(|path| path.as_str().to_string())
+        .collect()
+}
+
 #[async_trait]
 impl Stage for MapStage {
-    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) {
-        if let Some(body_str) = resp.get_body() {
-            let re = Regex::new(r"(?m)//[#@]\s*sourceMappingURL\s*=\s*(?P<map>[^\s]+)").unwrap();
-
-            let mut to_search = vec![];
-            if let Ok(base) = resp.get_url() {
-                for caps in re.captures_iter(&body_str) {
-                    if let Some(path) = caps.name("map") {
-                        if let Ok(url) = base.join(path.as_str()) {
-                            to_search.push(url);
-                        }
-                    }
+    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
+        let scanned = resp.clone();
+        let references = self
+            .pool
+            .run(&resp.path, resp.decoded_len(), move || scanned.get_body().map(map_references))
+            .await?;
+
+        // an oversized bundle is not searched, but the fallback may still find its map
+        let references = match references {
+            Some(Some(references)) => references,
+            Some(None) => return Ok(()),
+            None => vec![],
+        };
+
+        let mut to_search = vec![];
+        if let Ok(base) = resp.get_url() {
+            for path in references {
+                if let Ok(url) = base.join(&path) {
+                    to_search.push(url);
                 }
+            }
 
-                if to_search.is_empty() {
-                    println!("[Map] Nenhum sourceMappingURL explícito. Tentando fallback.");
-                    if let Ok(fallback) = base.join(&format!("{}.map", base.path())) {
-                        to_search.push(fallback);
-                    }
+            if to_search.is_empty() && self.options.fallback {
+                println!("[Map] Nenhum sourceMappingURL explícito. Tentando fallback.");
+                if let Ok(fallback) = base.join(&format!("{}.map", base.path())) {
+                    to_search.push(fallback);
                 }
             }
+        }
+
+        let client = Client::builder()
+            .user_agent(&self.options.user_agent)
+            .dns_resolver(Arc::new(self.host_map.clone()))
+            .build()?;
+
+        for search in to_search {
+            if let Some(content) = fetch_map(&client, search.as_str()).await {
+                let sources = self
+                    .pool
+                    .run(search.as_str(), content.len(), move || {
+                        extract_source_maps(content.as_bytes())
+                    })
+                    .await?
+                    .unwrap_or_default();
 
-            let client = Client::builder().user_agent("mboi-tata/0.1").build();
-
-            if let Ok(client) = client {
-                for search in to_search {
-                    if let Some(content) = fetch_map(&client, search.as_str()).await {
-                        let scheme = resp.scheme.clone();
-                        let host = resp.host.clone();
-                        for (name, content) in extract_source_maps(content.as_bytes()) {
-                            if let Ok(url) = search.join(&name) {
-                                dispatcher.emit(
-                                    StageId::SaveFile,
-                                    InterceptedResponse {
-                                        scheme: scheme.clone(),
-                                        host: host.clone(),
-                                        path: url.path().to_string(),
-                                        content_encoding: "identity".to_string(),
-                                        content_type: "application/javascript".into(),
-                                        body: content.as_bytes().to_vec(),
-                                    },
-                                );
-                            }
-                        }
+                // the map is never an event itself, but it is a hop between the bundle and its sources
+                let map = resp.provenance.child(resp.location(), StageId::Map);
+                for (name, content) in sources {
+                    if let Ok(url) = search.join(&name) {
+                        dispatcher.forward(
+                            StageId::Map,
+                            "sources",
+                            resp.derive_with(
+                                map.child(search.as_str(), StageId::Map),
+                                url.path().to_string(),
+                                "application/javascript",
+                                content.into_bytes(),
+                            ),
+                        ).await;
                     }
                 }
             }
-
-            dispatcher.emit(StageId::SaveFile, resp.clone());
-            dispatcher.emit(StageId::Scan, resp.clone());
-            dispatcher.emit(StageId::JsScan, resp);
         }
+
+        dispatcher.forward(StageId::Map, OUT, resp).await;
+        Ok(())
     }
 }
 
@@ -1,7 +1,10 @@
+pub mod archive_stage;
+pub mod dedup_stage;
 pub mod filter_stage;
 pub mod js;
 pub mod map_stage;
+pub mod plugin;
 pub mod save_file_stage;
 pub mod scan_js_stage;
 pub mod scan_stage;
-pub mod utils;
+pub mod session_stage;
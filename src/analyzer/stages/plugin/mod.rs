@@ -0,0 +1,226 @@
+pub mod process;
+pub mod wasm;
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::{
+    event::Dispatcher,
+    finding::{Confidence, Finding, Position},
+    intercepted::InterceptedResponse,
+    pipeline::OUT,
+    stage::{Stage, StageId},
+    stages::plugin::{process::ProcessStage, wasm::{WasmLimits, WasmStage}},
+};
+
+pub const DEFAULT_PLUGIN_TIMEOUT: u64 = 30;
+pub const DEFAULT_WASM_FUEL: u64 = 1_000_000_000;
+pub const DEFAULT_WASM_MEMORY_MB: usize = 64;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub module: Option<PathBuf>,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_fuel")]
+    pub fuel: u64,
+    #[serde(default = "default_memory_mb")]
+    pub memory_mb: usize,
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_PLUGIN_TIMEOUT
+}
+
+fn default_fuel() -> u64 {
+    DEFAULT_WASM_FUEL
+}
+
+fn default_memory_mb() -> usize {
+    DEFAULT_WASM_MEMORY_MB
+}
+
+impl PluginConfig {
+    pub fn stage(&self, id: StageId) -> anyhow::Result<Box<dyn Stage + Send + Sync>> {
+        let timeout = Duration::from_secs(self.timeout_secs);
+
+        match (&self.command, &self.module) {
+            (Some(command), None) => Ok(Box::new(ProcessStage::new(
+                id,
+                command.clone(),
+                self.args.clone(),
+                timeout,
+            ))),
+            (None, Some(module)) => Ok(Box::new(WasmStage::load(
+                id,
+                module,
+                WasmLimits {
+                    fuel: self.fuel,
+                    memory: self.memory_mb * 1024 * 1024,
+                    timeout,
+                },
+            )?)),
+            _ => bail!("o plugin {id} precisa de `command` ou `module`, não ambos"),
+        }
+    }
+}
+
+fn default_port() -> String {
+    OUT.to_string()
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExchangeMessage<'a> {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub id: u64,
+    pub url: Option<String>,
+    pub text: Option<&'a str>,
+    pub response: &'a InterceptedResponse,
+}
+
+impl<'a> ExchangeMessage<'a> {
+    pub fn new(id: u64, resp: &'a InterceptedResponse) -> Self {
+        Self {
+            kind: "exchange",
+            id,
+            url: resp.get_url().ok().map(|u| u.to_string()),
+            text: resp.get_body(),
+            response: resp,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PluginMessage {
+    Finding {
+        #[serde(default)]
+        id: u64,
+        label: String,
+        value: String,
+        confidence: Option<Confidence>,
+        line: Option<usize>,
+        column: Option<usize>,
+        detail: Option<String>,
+    },
+    Emit {
+        #[serde(default)]
+        id: u64,
+        #[serde(default = "default_port")]
+        port: String,
+        path: Option<String>,
+        content_type: Option<String>,
+        body: Option<String>,
+    },
+    Save {
+        #[serde(default)]
+        id: u64,
+        path: String,
+        body: String,
+    },
+    Done {
+        #[serde(default)]
+        id: u64,
+    },
+}
+
+impl PluginMessage {
+    pub fn id(&self) -> u64 {
+        match self {
+            PluginMessage::Finding { id, .. }
+            | PluginMessage::Emit { id, .. }
+            | PluginMessage::Save { id, .. }
+            | PluginMessage::Done { id } => *id,
+        }
+    }
+}
+
+pub struct PluginOutput {
+    from: StageId,
+    findings: Vec<Finding>,
+    emits: Vec<(String, InterceptedResponse)>,
+    saves: Vec<InterceptedResponse>,
+}
+
+impl PluginOutput {
+    pub fn new(from: StageId) -> Self {
+        Self {
+            from,
+            findings: vec![],
+            emits: vec![],
+            saves: vec![],
+        }
+    }
+
+    pub fn push(&mut self, message: PluginMessage, resp: &InterceptedResponse) {
+        match message {
+            PluginMessage::Finding {
+                label,
+                value,
+                confidence,
+                line,
+                column,
+                detail,
+                ..
+            } => {
+                let position = line.map(|line| Position {
+                    line,
+                    column: column.unwrap_or(1),
+                });
+                let mut finding = Finding::new(self.from, label, value, resp)
+                    .position(position)
+                    .confidence(confidence.unwrap_or(Confidence::Medium));
+                if let Some(detail) = detail {
+                    finding = finding.detail(detail);
+                }
+                self.findings.push(finding);
+            }
+            // without a path the plugin is just passing the exchange along
+            PluginMessage::Emit {
+                port,
+                path: Some(path),
+                content_type,
+                body,
+                ..
+            } => self.emits.push((
+                port,
+                resp.derive(
+                    self.from,
+                    path,
+                    &content_type.unwrap_or_default(),
+                    body.unwrap_or_default().into_bytes(),
+                ),
+            )),
+            PluginMessage::Emit { port, .. } => self.emits.push((port, resp.clone())),
+            PluginMessage::Save { path, body, .. } => {
+                self.saves
+                    .push(resp.derive(self.from, path, "", body.into_bytes()))
+            }
+            PluginMessage::Done { .. } => {}
+        }
+    }
+
+    pub async fn apply(self, dispatcher: &Dispatcher) {
+        for finding in self.findings {
+            println!("[!] [{}] {} em {}: {}", self.from, finding.kind, finding.host, finding.value);
+            dispatcher.report(finding);
+        }
+
+        for (port, emitted) in self.emits {
+            dispatcher.forward(self.from, &port, emitted).await;
+        }
+
+        // saves skip the routes, same as writing the file from the plugin itself
+        for saved in self.saves {
+            dispatcher.emit(StageId::SaveFile, saved).await;
+        }
+    }
+}
+
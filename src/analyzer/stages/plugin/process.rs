@@ -0,0 +1,242 @@
+use std::{
+    collections::HashMap,
+    process::Stdio,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, ChildStdin, ChildStdout, Command},
+    sync::mpsc::{UnboundedSender, unbounded_channel},
+    time::timeout,
+};
+
+use crate::analyzer::{
+    event::Dispatcher,
+    intercepted::InterceptedResponse,
+    stage::{Stage, StageId},
+    stages::plugin::{ExchangeMessage, PluginMessage, PluginOutput},
+};
+
+type Pending = Arc<Mutex<HashMap<u64, UnboundedSender<PluginMessage>>>>;
+
+struct Connection {
+    stdin: tokio::sync::Mutex<ChildStdin>,
+    pending: Pending,
+    alive: Arc<AtomicBool>,
+    _child: Child,
+}
+
+pub struct ProcessStage {
+    id: StageId,
+    command: String,
+    args: Vec<String>,
+    timeout: Duration,
+    next_id: AtomicU64,
+    conn: tokio::sync::Mutex<Option<Arc<Connection>>>,
+}
+
+impl ProcessStage {
+    pub fn new(id: StageId, command: String, args: Vec<String>, timeout: Duration) -> Self {
+        Self {
+            id,
+            command,
+            args,
+            timeout,
+            next_id: AtomicU64::new(1),
+            conn: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    // the plugin is spawned on first use and again whenever it dies
+    async fn connection(&self) -> anyhow::Result<Arc<Connection>> {
+        let mut conn = self.conn.lock().await;
+        if let Some(c) = conn.as_ref().filter(|c| c.alive.load(Ordering::SeqCst)) {
+            return Ok(c.clone());
+        }
+
+        println!(
+            "🔌 [Plugin] Iniciando {}: {} {}",
+            self.id,
+            self.command,
+            self.args.join(" ")
+        );
+
+        let mut child = Command::new(&self.command)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("não foi possível iniciar {}: {}", self.command, e))?;
+
+        let stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("plugin sem stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow::anyhow!("plugin sem stdout"))?;
+
+        let pending: Pending = Arc::new(Mutex::new(HashMap::new()));
+        let alive = Arc::new(AtomicBool::new(true));
+        tokio::spawn(read_messages(self.id, stdout, pending.clone(), alive.clone()));
+
+        let c = Arc::new(Connection {
+            stdin: tokio::sync::Mutex::new(stdin),
+            pending,
+            alive,
+            _child: child,
+        });
+        *conn = Some(c.clone());
+        Ok(c)
+    }
+}
+
+async fn read_messages(id: StageId, stdout: ChildStdout, pending: Pending, alive: Arc<AtomicBool>) {
+    let mut lines = BufReader::new(stdout).lines();
+
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("❌ [Plugin] Erro ao ler a saída de {id}: {e}");
+                break;
+            }
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let message: PluginMessage = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("⚠️ [Plugin] Mensagem inválida de {id}: {e}: {line}");
+                continue;
+            }
+        };
+
+        let mut pending = pending.lock().unwrap();
+        let done = matches!(message, PluginMessage::Done { .. });
+        let request = message.id();
+
+        if let Some(tx) = pending.get(&request) {
+            let _ = tx.send(message);
+        }
+        if done {
+            pending.remove(&request);
+        }
+    }
+
+    eprintln!("⚠️ [Plugin] {id} encerrou.");
+    alive.store(false, Ordering::SeqCst);
+    pending.lock().unwrap().clear();
+}
+
+#[async_trait]
+impl Stage for ProcessStage {
+    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
+        let conn = self.connection().await?;
+
+        let request = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let line = serde_json::to_string(&ExchangeMessage::new(request, &resp))?;
+
+        let (tx, mut rx) = unbounded_channel();
+        conn.pending.lock().unwrap().insert(request, tx);
+
+        let sent = {
+            let mut stdin = conn.stdin.lock().await;
+            match stdin.write_all(format!("{line}\n").as_bytes()).await {
+                Ok(_) => stdin.flush().await,
+                Err(e) => Err(e),
+            }
+        };
+        if let Err(e) = sent {
+            conn.pending.lock().unwrap().remove(&request);
+            anyhow::bail!("erro ao enviar exchange para {}: {}", self.id, e);
+        }
+
+        let mut output = PluginOutput::new(self.id);
+        let finished = timeout(self.timeout, async {
+            while let Some(message) = rx.recv().await {
+                if let PluginMessage::Done { .. } = message {
+                    return true;
+                }
+                output.push(message, &resp);
+            }
+            false
+        })
+        .await;
+
+        // like any failed event, a crash or timeout discards what the plugin had already sent
+        match finished {
+            Ok(true) => {
+                output.apply(&dispatcher).await;
+                Ok(())
+            }
+            Ok(false) => anyhow::bail!("{} encerrou antes de concluir a resposta", self.id),
+            Err(_) => {
+                conn.pending.lock().unwrap().remove(&request);
+                anyhow::bail!("{} excedeu {}s", self.id, self.timeout.as_secs())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::HeaderMap;
+
+    use super::*;
+    use crate::analyzer::{
+        event::QueuePolicy,
+        finding::Finding,
+        intercepted::ConnectionInfo,
+        sink::{FindingSink, FindingSinks},
+    };
+
+    struct Collect(Arc<Mutex<Vec<Finding>>>);
+
+    impl FindingSink for Collect {
+        fn report(&self, finding: &Finding) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(finding.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn a_plugin_that_dies_mid_response_reports_nothing() {
+        let script = r#"read line; echo '{"type":"finding","id":1,"label":"token","value":"abc"}'"#;
+        let stage = ProcessStage::new(
+            StageId::plugin("dies"),
+            "sh".into(),
+            vec!["-c".into(), script.into()],
+            Duration::from_secs(5),
+        );
+
+        let findings = Arc::new(Mutex::new(vec![]));
+        let (tx, _) = tokio::sync::broadcast::channel(1);
+        let dispatcher = Dispatcher::new(
+            Default::default(),
+            vec![],
+            FindingSinks::new(vec![Box::new(Collect(findings.clone()))]),
+            QueuePolicy::Block,
+            tx,
+        );
+        let resp = InterceptedResponse::new(
+            "/".into(),
+            "http".into(),
+            "example.com".into(),
+            &HeaderMap::new(),
+            Vec::new(),
+            ConnectionInfo::default(),
+        );
+
+        let err = stage.process(dispatcher, resp).await.unwrap_err();
+        assert!(err.to_string().contains("encerrou"), "{err}");
+        assert!(findings.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    fs,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use tokio::time::timeout;
+use wasmi::{
+    Caller, Config, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, core::TrapCode,
+};
+
+use crate::analyzer::{
+    event::Dispatcher,
+    intercepted::InterceptedResponse,
+    stage::{Stage, StageId},
+    stages::plugin::{ExchangeMessage, PluginMessage, PluginOutput},
+};
+
+const HOST_MODULE: &str = "mboitata";
+const MAX_ACTIONS: usize = 10_000;
+// a deliberately low guess for wasmi, used until runs of the module have been timed
+const INITIAL_FUEL_PER_SEC: u64 = 50_000_000;
+// shorter runs are too noisy to say how fast the guest burns fuel
+const MIN_TIMED_RUN: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy)]
+pub struct WasmLimits {
+    pub fuel: u64,
+    pub memory: usize,
+    pub timeout: Duration,
+}
+
+struct HostState {
+    id: StageId,
+    limits: StoreLimits,
+    actions: Vec<PluginMessage>,
+}
+
+pub struct WasmStage {
+    id: StageId,
+    engine: Engine,
+    module: Arc<Module>,
+    limits: WasmLimits,
+    fuel_per_sec: AtomicU64,
+}
+
+impl WasmStage {
+    pub fn load(id: StageId, path: &Path, limits: WasmLimits) -> anyhow::Result<Self> {
+        let wasm = fs::read(path).map_err(|e| anyhow!("não foi possível ler {:?}: {}", path, e))?;
+
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, &wasm)
+            .map_err(|e| anyhow!("módulo wasm {:?} inválido: {}", path, e))?;
+
+        println!("🧩 [Plugin] Módulo wasm {:?} carregado como {id}", path);
+        Ok(Self {
+            id,
+            engine,
+            module: Arc::new(module),
+            limits,
+            fuel_per_sec: AtomicU64::new(INITIAL_FUEL_PER_SEC),
+        })
+    }
+
+    // a blocking wasm call can't be interrupted, so the time limit is turned into fuel the guest runs out of
+    fn fuel_budget(&self) -> u64 {
+        let rate = self.fuel_per_sec.load(Ordering::Relaxed) as f64;
+        let by_time = (rate * self.limits.timeout.as_secs_f64()) as u64;
+        self.limits.fuel.min(by_time.max(1))
+    }
+
+    fn record_rate(&self, consumed: u64, elapsed: Duration) {
+        if elapsed < MIN_TIMED_RUN || consumed == 0 {
+            return;
+        }
+
+        let measured = (consumed as f64 / elapsed.as_secs_f64()) as u64;
+        let previous = self.fuel_per_sec.load(Ordering::Relaxed);
+        self.fuel_per_sec
+            .store((previous * 3 + measured) / 4, Ordering::Relaxed);
+    }
+}
+
+// each exchange gets a fresh instance, so nothing leaks between calls
+fn run(
+    id: StageId,
+    engine: &Engine,
+    module: &Module,
+    memory: usize,
+    fuel: u64,
+    input: &[u8],
+) -> (anyhow::Result<Vec<PluginMessage>>, u64) {
+    let state = HostState {
+        id,
+        limits: StoreLimitsBuilder::new()
+            .memory_size(memory)
+            .instances(1)
+            .build(),
+        actions: vec![],
+    };
+
+    let mut store = Store::new(engine, state);
+    store.limiter(|state| &mut state.limits);
+
+    let result = store
+        .set_fuel(fuel)
+        .map_err(|e| anyhow!("{e}"))
+        .and_then(|_| call(&mut store, engine, module, input));
+    let consumed = fuel.saturating_sub(store.get_fuel().unwrap_or(0));
+
+    (result.map(|_| store.into_data().actions), consumed)
+}
+
+fn call(store: &mut Store<HostState>, engine: &Engine, module: &Module, input: &[u8]) -> anyhow::Result<()> {
+    let mut linker = <Linker<HostState>>::new(engine);
+    linker.func_wrap(
+        HOST_MODULE,
+        "log",
+        |caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<(), wasmi::Error> {
+            let message = read_guest(&caller, ptr, len)?;
+            println!("[{}] {}", caller.data().id, String::from_utf8_lossy(&message));
+            Ok(())
+        },
+    )?;
+    linker.func_wrap(
+        HOST_MODULE,
+        "action",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<(), wasmi::Error> {
+            let raw = read_guest(&caller, ptr, len)?;
+            let action: PluginMessage = serde_json::from_slice(&raw)
+                .map_err(|e| wasmi::Error::new(format!("ação inválida: {e}")))?;
+
+            let actions = &mut caller.data_mut().actions;
+            if actions.len() >= MAX_ACTIONS {
+                return Err(wasmi::Error::new("limite de ações excedido"));
+            }
+            actions.push(action);
+            Ok(())
+        },
+    )?;
+
+    let instance = linker.instantiate(&mut *store, module)?.start(&mut *store)?;
+    let memory = instance
+        .get_memory(&*store, "memory")
+        .ok_or_else(|| anyhow!("o módulo não exporta `memory`"))?;
+    let alloc = instance.get_typed_func::<i32, i32>(&*store, "alloc")?;
+    let process = instance.get_typed_func::<(i32, i32), i32>(&*store, "process")?;
+
+    let len = i32::try_from(input.len())?;
+    let ptr = alloc.call(&mut *store, len)?;
+    memory
+        .write(&mut *store, ptr as u32 as usize, input)
+        .map_err(|e| anyhow!("{e}"))?;
+
+    let status = process.call(&mut *store, (ptr, len))?;
+    if status != 0 {
+        anyhow::bail!("process retornou {status}");
+    }
+    Ok(())
+}
+
+fn out_of_fuel(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<wasmi::Error>()
+        .and_then(|e| e.as_trap_code())
+        .is_some_and(|code| code == TrapCode::OutOfFuel)
+}
+
+fn read_guest(caller: &Caller<'_, HostState>, ptr: i32, len: i32) -> Result<Vec<u8>, wasmi::Error> {
+    let Some(Extern::Memory(memory)) = caller.get_export("memory") else {
+        return Err(wasmi::Error::new("o módulo não exporta `memory`"));
+    };
+
+    // bounds first: the guest picks `len`, and the host must never allocate past the guest's memory
+    let start = ptr as u32 as usize;
+    let Some(end) = usize::try_from(len).ok().and_then(|len| start.checked_add(len)) else {
+        return Err(wasmi::Error::new(format!("tamanho inválido: {len}")));
+    };
+    memory
+        .data(caller)
+        .get(start..end)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| wasmi::Error::new(format!("leitura fora da memória do módulo: {start}..{end}")))
+}
+
+#[async_trait]
+impl Stage for WasmStage {
+    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
+        let input = serde_json::to_vec(&ExchangeMessage::new(0, &resp))?;
+
+        let id = self.id;
+        let engine = self.engine.clone();
+        let module = self.module.clone();
+        let memory = self.limits.memory;
+        let fuel = self.fuel_budget();
+
+        let started = Instant::now();
+        let task = tokio::task::spawn_blocking(move || run(id, &engine, &module, memory, fuel, &input));
+
+        // the guest stops on its own once the fuel runs out; this only guards against a bad rate estimate
+        let (result, consumed) = match timeout(self.limits.timeout * 2, task).await {
+            Ok(joined) => joined?,
+            Err(_) => anyhow::bail!("{} excedeu {}s", self.id, self.limits.timeout.as_secs()),
+        };
+        self.record_rate(consumed, started.elapsed());
+
+        let actions = match result {
+            Err(e) if fuel < self.limits.fuel && out_of_fuel(&e) => {
+                anyhow::bail!("{} excedeu {}s", self.id, self.limits.timeout.as_secs())
+            }
+            other => other?,
+        };
+
+        let mut output = PluginOutput::new(self.id);
+        for action in actions {
+            output.push(action, &resp);
+        }
+        output.apply(&dispatcher).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::HeaderMap;
+
+    use super::*;
+    use crate::analyzer::{intercepted::ConnectionInfo, sink::FindingSinks};
+
+    const SPIN: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) i32.const 0)
+            (func (export "process") (param i32 i32) (result i32)
+                (loop $spin br $spin)
+                i32.const 0))
+    "#;
+
+    const HUGE_LOG: &str = r#"
+        (module
+            (import "mboitata" "log" (func $log (param i32 i32)))
+            (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) i32.const 0)
+            (func (export "process") (param i32 i32) (result i32)
+                (call $log (i32.const 0) (i32.const 2147483647))
+                i32.const 0))
+    "#;
+
+    fn load(name: &str, wat: &str) -> WasmStage {
+        let path = std::env::temp_dir().join(format!("mboitata-{name}-{}.wasm", std::process::id()));
+        fs::write(&path, wat::parse_str(wat).unwrap()).unwrap();
+
+        let stage = WasmStage::load(
+            StageId::plugin(name),
+            &path,
+            WasmLimits {
+                fuel: u64::MAX,
+                memory: 1024 * 1024,
+                timeout: Duration::from_secs(1),
+            },
+        )
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+        stage
+    }
+
+    async fn process(stage: &WasmStage) -> anyhow::Result<()> {
+        let (tx, _) = tokio::sync::broadcast::channel(1);
+        let dispatcher = Dispatcher::new(
+            Default::default(),
+            vec![],
+            FindingSinks::new(vec![]),
+            crate::analyzer::event::QueuePolicy::Block,
+            tx,
+        );
+        let resp = InterceptedResponse::new(
+            "/".into(),
+            "http".into(),
+            "example.com".into(),
+            &HeaderMap::new(),
+            Vec::new(),
+            ConnectionInfo::default(),
+        );
+
+        stage.process(dispatcher, resp).await
+    }
+
+    #[tokio::test]
+    async fn a_spinning_guest_stops_at_the_time_limit() {
+        let stage = load("spin", SPIN);
+
+        let started = Instant::now();
+        let err = process(&stage).await.unwrap_err();
+        assert!(err.to_string().contains("excedeu 1s"), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn reads_past_guest_memory_are_rejected_before_allocating() {
+        let stage = load("huge-log", HUGE_LOG);
+
+        let err = process(&stage).await.unwrap_err();
+        assert!(format!("{err:#}").contains("fora da memória"), "{err:#}");
+    }
+}
@@ -1,36 +1,43 @@
 use std::{fs, path::PathBuf};
 
+use anyhow::anyhow;
 use async_trait::async_trait;
+use serde::Deserialize;
 
 use crate::analyzer::{event::Dispatcher, intercepted::InterceptedResponse, stage::Stage};
 
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SaveFileOptions {
+    pub dir: Option<PathBuf>,
+}
+
 pub struct SaveFileStage {
     output_dir: PathBuf
 }
 
 impl SaveFileStage {
-    pub fn new(output_dir: PathBuf) -> Self {
-        Self { output_dir }
+    pub fn new(output_dir: PathBuf, options: SaveFileOptions) -> Self {
+        Self {
+            output_dir: options.dir.unwrap_or(output_dir),
+        }
     }
 }
 
 #[async_trait]
 impl Stage for SaveFileStage {
-    async fn process(&self, _: Dispatcher, resp: InterceptedResponse) {
-        if let Some(body) = resp.get_body() {
-            if let Some(dir) = resp.safe_join(&self.output_dir) {
-                if let Some(parent) = dir.parent() {
-                    if let Err(e) = fs::create_dir_all(&parent) {
-                        eprintln!("Erro ao criar diretório {:?}: {}", parent, e);
-                        return;
-                    }
-                }
-                
-                println!("[SaveFile] salvado o arquivo {:?}", &dir);
-                if let Err(e) = fs::write(&dir, &body) {
-                    eprintln!("Erro ao salvar o arquivo {:?}: {}", dir, e);
-                }
-            }
+    async fn process(&self, _: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
+        let (Some(body), Some(dir)) = (resp.get_body(), resp.safe_join(&self.output_dir)) else {
+            return Ok(());
+        };
+
+        if let Some(parent) = dir.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| anyhow!("erro ao criar diretório {:?}: {}", parent, e))?;
         }
+
+        println!("[SaveFile] salvado o arquivo {:?}", &dir);
+        fs::write(&dir, &body).map_err(|e| anyhow!("erro ao salvar o arquivo {:?}: {}", dir, e))?;
+        Ok(())
     }
 }
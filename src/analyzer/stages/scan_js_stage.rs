@@ -1,86 +1,74 @@
 use async_trait::async_trait;
 
 use crate::analyzer::{
+    cpu::CpuPool,
     event::Dispatcher,
+    finding::{Confidence, Finding},
     intercepted::InterceptedResponse,
     stage::{Stage, StageId},
-    stages::{js::analyzer::run_js_analysis, utils::file_name},
+    stages::js::analyzer::analyze_module,
 };
 
-pub struct ScanJsStage;
+pub struct ScanJsStage {
+    pool: CpuPool,
+}
 
 impl ScanJsStage {
-    pub fn new() -> Self {
-        Self
+    pub fn new(pool: CpuPool) -> Self {
+        Self { pool }
     }
 }
 
 #[async_trait]
 impl Stage for ScanJsStage {
-    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) {
-        if let Some(text) = resp.get_body() {
-            match run_js_analysis(&resp.path, text.as_str()) {
-                Ok(result) => {
-                    let scheme = resp.scheme.clone();
-                    let host = resp.host.clone();
-                    let path = file_name(&resp.path);
-
-                    if !result.vars.is_empty() {
-                        let mut vars = result
-                            .vars
-                            .iter()
-                            .map(|(k, v)| format!("{}={}", k, v))
-                            .collect::<Vec<_>>();
+    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
+        let analyzed = resp.clone();
+        // the parsed module is cached on the body, so other stages reuse it
+        let result = self
+            .pool
+            .run(&resp.path, resp.decoded_len(), move || {
+                analyzed
+                    .get_body()
+                    .map(|_| analyzed.js_ast().map(|ast| analyze_module(&ast)))
+            })
+            .await?;
 
-                        vars.sort();
-                        let content = vars.join("\n");
+        if let Some(Some(result)) = result {
+            match result {
+                Ok(result) => {
+                    let mut vars = result.vars.iter().collect::<Vec<_>>();
+                    vars.sort();
 
-                        let label = "VARS";
-                        dispatcher.emit(
-                            StageId::SaveFile,
-                            InterceptedResponse {
-                                scheme: scheme.clone(),
-                                host: host.clone(),
-                                path: format!("findings/{}/{}", &path, label),
-                                content_encoding: "identity".into(),
-                                content_type: "".into(),
-                                body: content.as_bytes().to_vec(),
-                            },
+                    for (k, v) in vars {
+                        dispatcher.report(
+                            Finding::new(StageId::JsScan, "VARS", format!("{}={}", k, v), &resp)
+                                .confidence(Confidence::Low),
                         );
                     }
 
-                    if !result.calls.is_empty() {
-                        let content = &result
-                            .calls
-                            .iter()
-                            .map(|c| {
-                                let auth = c.authorization.as_deref().unwrap_or("-");
-                                format!("Url: {}\nMethod: {}\nAuth: {}\n", c.url, c.method, auth)
-                            })
-                            .collect::<Vec<_>>()
-                            .join("==================================================\n");
-
-                        let label = "CALLS";
-                        dispatcher.emit(
-                            StageId::SaveFile,
-                            InterceptedResponse {
-                                scheme: scheme.clone(),
-                                host: host.clone(),
-                                path: format!("findings/{}/{}", &path, label),
-                                content_encoding: "identity".into(),
-                                content_type: "".into(),
-                                body: content.as_bytes().to_vec(),
-                            },
+                    for call in &result.calls {
+                        let mut finding = Finding::new(
+                            StageId::JsScan,
+                            "CALLS",
+                            format!("{} {}", call.method, call.url),
+                            &resp,
                         );
+                        if let Some(auth) = &call.authorization {
+                            finding = finding.detail(format!("Auth: {auth}"));
+                        }
+                        dispatcher.report(finding);
                     }
                 }
                 Err(err) => {
-                    eprintln!(
-                        "Erro ao extrair informações do javascript {}: {}",
-                        resp.path, err
+                    anyhow::bail!(
+                        "erro ao extrair informações do javascript {}: {}",
+                        resp.path,
+                        err
                     );
                 }
             }
         }
+
+        Ok(())
     }
 }
@@ -1,83 +1,95 @@
-use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
 
 use crate::analyzer::{
+    cpu::CpuPool,
     event::Dispatcher,
+    finding::{Confidence, Finding, Locator, Position},
     intercepted::InterceptedResponse,
     stage::{Stage, StageId},
-    stages::utils::file_name,
 };
 use async_trait::async_trait;
 use regex::Regex;
 
-pub struct ScanStage;
+static PATTERNS: LazyLock<Vec<(&str, Regex, Confidence)>> = LazyLock::new(|| {
+    vec![
+        (
+            "JWT",
+            Regex::new(r"(eyJ[A-Za-z0-9-_]+\.[A-Za-z0-9-_]+\.[A-Za-z0-9-_]+)").unwrap(),
+            Confidence::High,
+        ),
+        (
+            "Bearer Token",
+            Regex::new(r"(?i)bearer\s+([A-Za-z0-9\-_\.=]+)").unwrap(),
+            Confidence::High,
+        ),
+        (
+            "API Key",
+            Regex::new(r#"(?i)(api|access|secret)[_\-]?key["']?\s*[:=]\s*["']?[A-Za-z0-9\-_]{16,}"#)
+                .unwrap(),
+            Confidence::Medium,
+        ),
+        (
+            "URL",
+            Regex::new(r#"https?://[^\s"'<>]+"#).unwrap(),
+            Confidence::Low,
+        ),
+    ]
+});
+
+pub struct ScanStage {
+    pool: CpuPool,
+}
 
 impl ScanStage {
-    pub fn new() -> Self {
-        Self
+    pub fn new(pool: CpuPool) -> Self {
+        Self { pool }
     }
 }
 
-#[async_trait]
-impl Stage for ScanStage {
-    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) {
-        if let Some(body) = resp.get_body() {
-            let token_regex =
-                Regex::new(r"(eyJ[A-Za-z0-9-_]+\.[A-Za-z0-9-_]+\.[A-Za-z0-9-_]+)").unwrap();
-            let bearer_regex = Regex::new(r"(?i)bearer\s+([A-Za-z0-9\-_\.=]+)").unwrap();
-            let api_key_regex = Regex::new(
-                r#"(?i)(api|access|secret)[_\-]?key["']?\s*[:=]\s*["']?[A-Za-z0-9\-_]{16,}"#,
-            )
-            .unwrap();
-            let url_regex = Regex::new(r#"https?://[^\s"'<>]+"#).unwrap();
-
-            let mut findings: HashMap<&str, HashSet<String>> = HashMap::new();
-
-            for (label, regex) in &[
-                ("JWT", token_regex),
-                ("Bearer Token", bearer_regex),
-                ("API Key", api_key_regex),
-                ("URL", url_regex),
-            ] {
-                for mat in regex.find_iter(body.as_str()) {
-                    println!(
-                        "[!] Possível {} detectado em resposta de {}: {}",
-                        label,
-                        resp.host,
-                        mat.as_str()
-                    );
+fn scan(body: &str) -> Vec<(&'static str, String, Position, Confidence)> {
+    let mut matches = PATTERNS
+        .iter()
+        .flat_map(|(label, regex, confidence)| {
+            regex
+                .find_iter(body)
+                .map(move |mat| (mat.start(), *label, mat.as_str().to_string(), *confidence))
+        })
+        .collect::<Vec<_>>();
 
-                    findings
-                        .entry(label)
-                        .or_default()
-                        .insert(mat.as_str().to_string());
-                }
-            }
+    // in body order, so every match is located in one pass instead of rescanning from the start
+    matches.sort_by_key(|(start, ..)| *start);
+    let mut locator = Locator::new(body);
 
-            let scheme = resp.scheme.clone();
-            let host = resp.host.clone();
-            let path = file_name(&resp.path);
+    matches
+        .into_iter()
+        .map(|(start, label, value, confidence)| (label, value, locator.locate(start), confidence))
+        .collect()
+}
 
-            for (label, items) in findings {
-                if items.is_empty() {
-                    continue;
-                }
+#[async_trait]
+impl Stage for ScanStage {
+    async fn process(&self, dispatcher: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
+        let scanned = resp.clone();
+        let matches = self
+            .pool
+            .run(&resp.path, resp.decoded_len(), move || scanned.get_body().map(scan))
+            .await?;
 
-                let mut v = items.iter().cloned().collect::<Vec<String>>();
-                v.sort();
+        if let Some(Some(matches)) = matches {
+            for (label, value, position, confidence) in matches {
+                println!(
+                    "[!] Possível {} detectado em resposta de {}: {}",
+                    label, resp.host, value
+                );
 
-                let content = v.join("\n");
-                dispatcher.emit(
-                    StageId::SaveFile,
-                    InterceptedResponse {
-                        scheme: scheme.clone(),
-                        host: host.clone(),
-                        path: format!("findings/{}/{}", path, label),
-                        content_encoding: "identity".into(),
-                        content_type: "".into(),
-                        body: content.as_bytes().to_vec(),
-                    },
+                dispatcher.report(
+                    Finding::new(StageId::Scan, label, value, &resp)
+                        .position(Some(position))
+                        .confidence(confidence),
                 );
             }
         }
+
+        Ok(())
     }
 }
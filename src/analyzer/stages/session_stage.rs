@@ -0,0 +1,26 @@
+use async_trait::async_trait;
+
+use crate::{
+    analyzer::{event::Dispatcher, intercepted::InterceptedResponse, stage::Stage},
+    session::Session,
+};
+
+pub struct SessionStage {
+    session: Session,
+}
+
+impl SessionStage {
+    pub fn new(session: Session) -> Self {
+        Self { session }
+    }
+}
+
+#[async_trait]
+impl Stage for SessionStage {
+    async fn process(&self, _: Dispatcher, resp: InterceptedResponse) -> anyhow::Result<()> {
+        let session = self.session.clone();
+        // sqlite calls block, keep them off the async workers
+        tokio::task::spawn_blocking(move || session.record_exchange(&resp)).await??;
+        Ok(())
+    }
+}
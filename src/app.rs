@@ -0,0 +1,196 @@
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use tokio::{
+    signal,
+    sync::{broadcast, mpsc},
+};
+
+use crate::{
+    allow_list::AllowList,
+    analyzer::{
+        intercepted::InterceptedResponse, setup::initialize_stages, stages::archive_stage::ARCHIVE_FILE,
+    },
+    ca,
+    config::{self, Config},
+    consumer,
+    proxy::{
+        ProxyContext,
+        certs::{CertificateManager, load_or_create_ca},
+        pinning::PinningTracker,
+        start_proxy,
+    },
+    recon::Recon,
+    repeater,
+    session::{self, Session},
+};
+
+// everything the mboitata binary does once the arguments are parsed
+pub async fn run(config: Config, allow_list: AllowList) -> anyhow::Result<()> {
+    if let Some(config::Command::Ca(command)) = &config.command {
+        return ca::run(command, &config.get_certs_dir(), &allow_list);
+    }
+
+    if let Some(config::Command::Repeat(args)) = &config.command {
+        let archive = args
+            .archive
+            .clone()
+            .unwrap_or_else(|| config.output.join(ARCHIVE_FILE));
+        return repeater::run(args, &archive, config.get_host_map()?).await;
+    }
+
+    if let Some(config::Command::Session(command)) = &config.command {
+        return session::report::run(command, &config.get_session_path());
+    }
+
+    let (tx, rx) = mpsc::channel::<InterceptedResponse>(1000);
+    let (kill, _): (broadcast::Sender<()>, broadcast::Receiver<()>) = broadcast::channel(1);
+
+    let cert_dir = config.get_certs_dir();
+    let (ca_cert_pem, ca_key_pem) = load_or_create_ca(&cert_dir)?;
+
+    let host_map = config.get_host_map()?;
+    if !host_map.is_empty() {
+        println!("[INFO] Host map ativo para conexões upstream.");
+    }
+
+    let cert_manager = Arc::new(
+        CertificateManager::new(ca_cert_pem.as_str(), ca_key_pem.as_str())?
+            .with_wildcards(config.wildcard_certs)
+            .with_upstream_mirror(config.mirror_upstream_certs.then(|| host_map.clone()))
+            .with_cache_size(config.cert_cache_size)
+            .with_storage(&cert_dir)?,
+    );
+
+    if !allow_list.domains().is_empty() {
+        let cert_manager = cert_manager.clone();
+        let domains = allow_list.domains().to_vec();
+        tokio::spawn(async move { cert_manager.prewarm(&domains).await });
+    }
+
+    let recon = Recon::new(&config.output, allow_list.clone());
+    let breakpoints = config.get_breakpoints()?;
+
+    let pipeline = config.get_pipeline()?;
+    let dispatch_options = config.get_dispatch_options(&pipeline)?;
+    let session = config.get_session()?;
+
+    let (dispatcher, stage_handle) = initialize_stages(
+        allow_list,
+        config.output,
+        host_map.clone(),
+        &dispatch_options,
+        &pipeline,
+        session.clone(),
+    )?;
+    let pinning = PinningTracker::new(config.pinning_threshold, recon.clone()).reporting(dispatcher.clone());
+
+    let proxy_server = format!("0.0.0.0:{}", &config.port);
+    let kill_signal = kill.clone();
+    let urls_futures = browse(config.urls.clone(), proxy_server.clone(), kill_signal, session.clone())?;
+
+    let breakpoint_api: Pin<Box<dyn Future<Output = ()> + Send>> = if breakpoints.is_empty() {
+        Box::pin(async {})
+    } else {
+        let api = breakpoints.clone().serve_api(config.break_api.clone(), kill.subscribe());
+        Box::pin(async move {
+            if let Err(e) = api.await {
+                eprintln!("❌ API de breakpoints falhou: {e}");
+            }
+        })
+    };
+
+    let kill_signal = kill.clone();
+    let mut kill_receiver = kill_signal.subscribe();
+    let kill_listener = tokio::spawn(async move {
+        tokio::select! {
+            _ = signal::ctrl_c() => {
+                println!("🧨 Ctrl+C detectado. Enviando kill...");
+                let _ = kill_signal.send(());
+            }
+
+            _ = kill_receiver.recv() => {
+                println!("📴 Kill já foi enviado. kill_listener encerrando.");
+            }
+        }
+    });
+
+    let _ = tokio::join!(
+        start_proxy(
+            tx,
+            kill.subscribe(),
+            ProxyContext {
+                cert_manager,
+                host_map,
+                recon,
+                pinning,
+                breakpoints,
+            },
+            &proxy_server,
+            Duration::from_secs(config.drain_timeout),
+        ),
+        consumer::start_consumer(rx, dispatcher),
+        urls_futures,
+        breakpoint_api,
+        stage_handle,
+        kill_listener
+    );
+
+    if let Some(session) = session {
+        tokio::task::spawn_blocking(move || session.flush()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "navigator")]
+fn browse(
+    urls: Option<Vec<String>>,
+    proxy_server: String,
+    kill: broadcast::Sender<()>,
+    session: Option<Session>,
+) -> anyhow::Result<Pin<Box<dyn Future<Output = ()> + Send>>> {
+    let Some(urls) = urls.filter(|urls| !urls.is_empty()) else {
+        println!("⚠️ Nenhuma URL fornecida. Navegador não será executado.");
+        return Ok(Box::pin(async {}));
+    };
+
+    let Some(session) = session else {
+        println!("🌐 Iniciando navegador com URLs...");
+        return Ok(Box::pin(crate::navigator::run(urls, 4, proxy_server, kill, Arc::new(|_| {}))));
+    };
+
+    session.enqueue_urls(&urls)?;
+    let pending = session.pending_urls(&urls)?;
+    if pending.is_empty() {
+        println!("✅ [Session] Todas as URLs já foram visitadas nesta sessão. Navegador não será executado.");
+        return Ok(Box::pin(async {}));
+    }
+    if pending.len() < urls.len() {
+        println!(
+            "[Session] {} URL(s) já visitada(s). Retomando com {}.",
+            urls.len() - pending.len(),
+            pending.len()
+        );
+    }
+
+    let on_visited = Arc::new(move |url: &str| {
+        if let Err(e) = session.mark_visited(url) {
+            eprintln!("❌ [Session] Erro ao marcar {url} como visitada: {e}");
+        }
+    });
+
+    println!("🌐 Iniciando navegador com URLs...");
+    Ok(Box::pin(crate::navigator::run(pending, 4, proxy_server, kill, on_visited)))
+}
+
+#[cfg(not(feature = "navigator"))]
+fn browse(
+    urls: Option<Vec<String>>,
+    _: String,
+    _: broadcast::Sender<()>,
+    _: Option<Session>,
+) -> anyhow::Result<Pin<Box<dyn Future<Output = ()> + Send>>> {
+    if urls.is_some_and(|urls| !urls.is_empty()) {
+        println!("⚠️ Compilado sem a feature `navigator`. As URLs não serão visitadas.");
+    }
+    Ok(Box::pin(async {}))
+}
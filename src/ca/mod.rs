@@ -0,0 +1,162 @@
+use std::{
+    fs,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, bail};
+use p12_keystore::{Certificate, KeyStore, KeyStoreEntry, PrivateKeyChain};
+use pki_types::{CertificateDer, PrivatePkcs8KeyDer, pem::PemObject};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    allow_list::AllowList,
+    config::{CaCommand, ExportFormat},
+    proxy::certs::{
+        CA_CERT_FILE, CA_KEY_FILE, CaOptions, clear_leafs, create_ca_certificate, write_private_key,
+    },
+};
+
+const NSS_NICKNAME: &str = "Mboi Tata Proxy Root CA";
+
+pub fn run(command: &CaCommand, certs_dir: &Path, allow_list: &AllowList) -> anyhow::Result<()> {
+    match command {
+        CaCommand::Generate { params, force } => {
+            if certs_dir.join(CA_CERT_FILE).exists() && !force {
+                bail!(
+                    "já existe uma CA em {:?}. Use --force ou `mboitata ca rotate` para substituí-la",
+                    certs_dir
+                );
+            }
+            generate(certs_dir, &params.options(allow_list)?)
+        }
+        CaCommand::Rotate { params } => rotate(certs_dir, &params.options(allow_list)?),
+        CaCommand::Export {
+            format,
+            out,
+            password,
+        } => export(certs_dir, *format, out, password),
+        CaCommand::Fingerprint => {
+            let der = read_ca_der(certs_dir)?;
+            println!("SHA-256: {}", fingerprint(&der));
+            Ok(())
+        }
+        CaCommand::Install { nss_db, trust_dir } => match (nss_db, trust_dir) {
+            (Some(db), _) => install_nss(certs_dir, db),
+            (_, Some(dir)) => install_trust_dir(certs_dir, dir),
+            _ => bail!("informe --nss-db ou --trust-dir"),
+        },
+    }
+}
+
+fn generate(certs_dir: &Path, options: &CaOptions) -> anyhow::Result<()> {
+    fs::create_dir_all(certs_dir)?;
+
+    let (cert, key) = create_ca_certificate(options)?;
+    fs::write(certs_dir.join(CA_CERT_FILE), &cert)?;
+    write_private_key(&certs_dir.join(CA_KEY_FILE), &key)?;
+    clear_leafs(certs_dir)?;
+
+    println!("🔐 CA '{}' gerada em {:?}", options.common_name, certs_dir);
+    if !options.permitted_domains.is_empty() {
+        println!("[INFO] Name constraints: {:?}", options.permitted_domains);
+    }
+    println!("SHA-256: {}", fingerprint(&read_ca_der(certs_dir)?));
+    Ok(())
+}
+
+fn rotate(certs_dir: &Path, options: &CaOptions) -> anyhow::Result<()> {
+    let ca_cert_path = certs_dir.join(CA_CERT_FILE);
+    let ca_key_path = certs_dir.join(CA_KEY_FILE);
+
+    if ca_cert_path.exists() {
+        let stamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let backup = certs_dir.join(format!("backup-{stamp}"));
+        fs::create_dir_all(&backup)?;
+
+        fs::rename(&ca_cert_path, backup.join(CA_CERT_FILE))?;
+        if ca_key_path.exists() {
+            fs::rename(&ca_key_path, backup.join(CA_KEY_FILE))?;
+        }
+        println!("📦 CA anterior guardada em {:?}", backup);
+    }
+
+    generate(certs_dir, options)
+}
+
+fn read_ca_der(certs_dir: &Path) -> anyhow::Result<CertificateDer<'static>> {
+    let path = certs_dir.join(CA_CERT_FILE);
+    let pem = fs::read(&path).map_err(|e| anyhow!("não foi possível ler {:?}: {}", path, e))?;
+    Ok(CertificateDer::from_pem_slice(&pem)?)
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    Sha256::digest(der)
+        .iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn export(certs_dir: &Path, format: ExportFormat, out: &Path, password: &str) -> anyhow::Result<()> {
+    let der = read_ca_der(certs_dir)?;
+
+    let data = match format {
+        ExportFormat::Pem => fs::read(certs_dir.join(CA_CERT_FILE))?,
+        ExportFormat::Der => der.to_vec(),
+        ExportFormat::P12 => {
+            let key_pem = fs::read(certs_dir.join(CA_KEY_FILE))?;
+            let key = PrivatePkcs8KeyDer::from_pem_slice(&key_pem)?;
+
+            let cert = Certificate::from_der(&der)?;
+            let local_key_id = Sha256::digest(&der);
+            let chain = PrivateKeyChain::new(key.secret_pkcs8_der(), local_key_id, [cert]);
+
+            let mut store = KeyStore::new();
+            store.add_entry("mboitata", KeyStoreEntry::PrivateKeyChain(chain));
+            store.writer(password).write()?
+        }
+    };
+
+    if format == ExportFormat::P12 {
+        write_private_key(out, &data)?;
+    } else {
+        fs::write(out, &data)?;
+    }
+
+    println!("📤 CA exportada ({:?}) para {:?}", format, out);
+    Ok(())
+}
+
+fn install_nss(certs_dir: &Path, db: &Path) -> anyhow::Result<()> {
+    let cert_path = certs_dir.join(CA_CERT_FILE);
+    read_ca_der(certs_dir)?;
+
+    let status = Command::new("certutil")
+        .arg("-d")
+        .arg(format!("sql:{}", db.display()))
+        .args(["-A", "-t", "C,,", "-n", NSS_NICKNAME, "-i"])
+        .arg(&cert_path)
+        .status()
+        .map_err(|e| anyhow!("não foi possível executar certutil (libnss3-tools): {e}"))?;
+
+    if !status.success() {
+        bail!("certutil terminou com {status}");
+    }
+
+    println!("✅ CA instalada no banco NSS {:?}", db);
+    Ok(())
+}
+
+fn install_trust_dir(certs_dir: &Path, dir: &Path) -> anyhow::Result<()> {
+    read_ca_der(certs_dir)?;
+
+    fs::create_dir_all(dir)?;
+    let target = dir.join("mboitata-ca.crt");
+    fs::copy(certs_dir.join(CA_CERT_FILE), &target)?;
+
+    println!("✅ CA copiada para {:?}", target);
+    println!("[TIP] Rode `update-ca-certificates` (ou equivalente) para atualizar o trust store.");
+    Ok(())
+}
@@ -1,9 +1,27 @@
-use std::{io::{self, BufRead}, path::PathBuf};
+use std::{io::{self, BufRead}, path::PathBuf, time::Duration};
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use regex::Regex;
 use url::Url;
 
-use crate::allow_list::AllowList;
+use crate::{
+    allow_list::AllowList,
+    analyzer::{
+        cpu::{CpuLimits, DEFAULT_ANALYSIS_TIMEOUT, DEFAULT_MAX_ANALYSIS_SIZE, default_workers},
+        dead_letter::DEAD_LETTER_FILE,
+        event::QueuePolicy,
+        pipeline::PipelineConfig,
+        router::{DEFAULT_MAX_CONCURRENCY, DEFAULT_QUEUE_CAPACITY, DEFAULT_STAGE_TIMEOUT, DispatchOptions},
+        finding::Confidence,
+        sink::SinkKind,
+    },
+    host_map::{HostMap, HostMapping, parse_hosts_file},
+    proxy::{
+        breakpoint::Breakpoints,
+        certs::{CaOptions, KeyAlgorithm},
+    },
+    session::{SESSION_FILE, Session},
+};
 
 #[derive(Debug, Parser)]
 #[command(
@@ -12,23 +30,253 @@ use crate::allow_list::AllowList;
     about = "HTTP(S) MITM proxy para capturar e analisar JS/JS.map"
 )]
 pub struct Config {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     #[arg(short, long, value_delimiter = ',')]
     pub urls: Option<Vec<String>>,
 
     #[arg(short, long, env = "MBOITATA_PORT", default_value = "8085")]
     pub port: String,
 
-    #[arg(short, long, env = "MBOITATA_OUTPUT", default_value = "output")]
+    #[arg(short, long, env = "MBOITATA_OUTPUT", default_value = "output", global = true)]
     pub output: PathBuf,
 
-    #[arg(short, long, env = "MBOITATA_CERTS")]
+    #[arg(short, long, env = "MBOITATA_CERTS", global = true)]
     pub certs_dir: Option<String>,
 
     #[arg(long, default_value_t = false)]
     pub pretty: bool,
 
-    #[arg(long, env = "MB_ALLOWLIST", value_delimiter = ',')]
+    #[arg(long, default_value_t = false)]
+    pub wildcard_certs: bool,
+
+    #[arg(long, default_value_t = false)]
+    pub mirror_upstream_certs: bool,
+
+    #[arg(long, default_value_t = 1000)]
+    pub cert_cache_size: usize,
+
+    #[arg(long, default_value_t = 3)]
+    pub pinning_threshold: u32,
+
+    #[arg(long, env = "MB_ALLOWLIST", value_delimiter = ',', global = true)]
     pub allow_list: Vec<String>,
+
+    #[arg(long, env = "MBOITATA_HOST_MAP", value_delimiter = ',', global = true)]
+    pub host_map: Vec<String>,
+
+    #[arg(long, env = "MBOITATA_HOSTS_FILE", global = true)]
+    pub hosts_file: Option<PathBuf>,
+
+    #[arg(long, env = "MBOITATA_DRAIN_TIMEOUT", default_value_t = 10)]
+    pub drain_timeout: u64,
+
+    #[arg(long, env = "MBOITATA_PIPELINE")]
+    pub pipeline: Option<PathBuf>,
+
+    #[arg(long, value_delimiter = ',')]
+    pub stage_workers: Vec<String>,
+
+    #[arg(long, default_value_t = DEFAULT_MAX_CONCURRENCY)]
+    pub max_concurrency: usize,
+
+    #[arg(long, default_value_t = DEFAULT_QUEUE_CAPACITY)]
+    pub queue_capacity: usize,
+
+    #[arg(long, value_enum, default_value_t = QueuePolicy::Block)]
+    pub queue_policy: QueuePolicy,
+
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [SinkKind::Text, SinkKind::Json])]
+    pub finding_sinks: Vec<SinkKind>,
+
+    #[arg(long, default_value_t = DEFAULT_STAGE_TIMEOUT)]
+    pub stage_timeout: u64,
+
+    #[arg(long, default_value_t = default_workers())]
+    pub cpu_workers: usize,
+
+    #[arg(long, default_value_t = DEFAULT_MAX_ANALYSIS_SIZE)]
+    pub max_analysis_size: usize,
+
+    #[arg(long, default_value_t = DEFAULT_ANALYSIS_TIMEOUT)]
+    pub analysis_timeout: u64,
+
+    #[arg(long)]
+    pub break_request: Vec<String>,
+
+    #[arg(long)]
+    pub break_response: Vec<String>,
+
+    #[arg(long, default_value_t = 120)]
+    pub break_timeout: u64,
+
+    #[arg(long, env = "MBOITATA_BREAK_API", default_value = "127.0.0.1:8086")]
+    pub break_api: String,
+
+    #[arg(long, env = "MBOITATA_SESSION", global = true)]
+    pub session: Option<PathBuf>,
+
+    #[arg(long, default_value_t = false, conflicts_with = "session")]
+    pub no_session: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    #[command(about = "Reenvia uma requisição capturada com modificações")]
+    Repeat(RepeatArgs),
+
+    #[command(subcommand, about = "Gerencia a CA usada para forjar certificados")]
+    Ca(CaCommand),
+
+    #[command(subcommand, about = "Consulta o banco da sessão")]
+    Session(SessionCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionCommand {
+    #[command(about = "Mostra quantos registros há em cada tabela")]
+    Summary,
+
+    #[command(about = "Lista os hosts vistos")]
+    Hosts,
+
+    #[command(about = "Lista os endpoints vistos")]
+    Endpoints {
+        #[arg(long)]
+        host: Option<String>,
+    },
+
+    #[command(about = "Lista os findings")]
+    Findings {
+        #[arg(long)]
+        host: Option<String>,
+
+        #[arg(long)]
+        kind: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = Confidence::Low)]
+        min_confidence: Confidence,
+    },
+
+    #[command(about = "Executa uma consulta SQL de leitura")]
+    Query { sql: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CaCommand {
+    #[command(about = "Gera uma nova CA")]
+    Generate {
+        #[command(flatten)]
+        params: CaParams,
+
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+
+    #[command(about = "Substitui a CA atual, guardando um backup e descartando os certificados forjados")]
+    Rotate {
+        #[command(flatten)]
+        params: CaParams,
+    },
+
+    #[command(about = "Exporta o certificado da CA")]
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Pem)]
+        format: ExportFormat,
+
+        #[arg(long)]
+        out: PathBuf,
+
+        #[arg(long, env = "MBOITATA_P12_PASSWORD", default_value = "")]
+        password: String,
+    },
+
+    #[command(about = "Mostra o fingerprint SHA-256 da CA")]
+    Fingerprint,
+
+    #[command(about = "Instala a CA em um banco NSS ou diretório de confiança")]
+    Install {
+        #[arg(long, required_unless_present = "trust_dir")]
+        nss_db: Option<PathBuf>,
+
+        #[arg(long, conflicts_with = "nss_db")]
+        trust_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct CaParams {
+    #[arg(long, default_value = "Mboi Tata Proxy Root CA")]
+    pub cn: String,
+
+    #[arg(long, default_value = "Mboi Tata")]
+    pub org: String,
+
+    #[arg(long, value_enum, default_value_t = KeyAlgorithm::EcdsaP256)]
+    pub key_alg: KeyAlgorithm,
+
+    #[arg(long)]
+    pub validity_days: Option<u32>,
+
+    #[arg(long, default_value_t = false)]
+    pub name_constraints: bool,
+}
+
+impl CaParams {
+    pub fn options(&self, allow_list: &AllowList) -> anyhow::Result<CaOptions> {
+        if self.name_constraints && allow_list.domains().is_empty() {
+            anyhow::bail!("--name-constraints exige uma --allow-list com os domínios do escopo");
+        }
+
+        Ok(CaOptions {
+            common_name: self.cn.clone(),
+            organization: self.org.clone(),
+            key_algorithm: self.key_alg,
+            validity_days: self.validity_days,
+            permitted_domains: match self.name_constraints {
+                true => allow_list.domains().to_vec(),
+                false => vec![],
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Der,
+    Pem,
+    P12,
+}
+
+#[derive(Debug, Args)]
+pub struct RepeatArgs {
+    #[arg(long)]
+    pub id: Option<u64>,
+
+    #[arg(long, default_value_t = false)]
+    pub list: bool,
+
+    #[arg(long)]
+    pub archive: Option<PathBuf>,
+
+    #[arg(short = 'X', long)]
+    pub method: Option<String>,
+
+    #[arg(short = 'H', long = "header")]
+    pub headers: Vec<String>,
+
+    #[arg(long)]
+    pub remove_header: Vec<String>,
+
+    #[arg(long = "param")]
+    pub params: Vec<String>,
+
+    #[arg(long, conflicts_with = "body_file")]
+    pub body: Option<String>,
+
+    #[arg(long)]
+    pub body_file: Option<PathBuf>,
 }
 
 impl Config {
@@ -43,12 +291,93 @@ impl Config {
             }
         }
     }
+
+    pub fn get_host_map(&self) -> anyhow::Result<HostMap> {
+        let mut entries = match &self.hosts_file {
+            Some(path) => parse_hosts_file(path)?,
+            None => vec![],
+        };
+
+        for entry in &self.host_map {
+            entries.push(HostMapping::parse(entry)?);
+        }
+
+        Ok(HostMap::new(entries))
+    }
+
+    pub fn get_dispatch_options(&self, pipeline: &PipelineConfig) -> anyhow::Result<DispatchOptions> {
+        let stage_workers = self
+            .stage_workers
+            .iter()
+            .map(|entry| {
+                let (stage, workers) = entry.split_once('=').ok_or_else(|| {
+                    anyhow::anyhow!("--stage-workers inválido '{entry}', esperado stage=N")
+                })?;
+                Ok((pipeline.resolve(stage)?, workers.trim().parse()?))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(DispatchOptions {
+            stage_workers,
+            max_concurrency: self.max_concurrency,
+            queue_capacity: self.queue_capacity,
+            queue_policy: self.queue_policy,
+            spill_dir: self.output.join("spill"),
+            finding_sinks: self.finding_sinks.clone(),
+            stage_timeout: Duration::from_secs(self.stage_timeout),
+            dead_letter: self.output.join(DEAD_LETTER_FILE),
+            cpu: CpuLimits {
+                workers: self.cpu_workers,
+                max_size: self.max_analysis_size,
+                timeout: Duration::from_secs(self.analysis_timeout),
+            },
+        })
+    }
+
+    pub fn get_session_path(&self) -> PathBuf {
+        self.session
+            .clone()
+            .unwrap_or_else(|| self.output.join(SESSION_FILE))
+    }
+
+    pub fn get_session(&self) -> anyhow::Result<Option<Session>> {
+        if self.no_session {
+            return Ok(None);
+        }
+
+        let path = self.get_session_path();
+        let session = Session::open(&path)?;
+        println!("[Session] Gravando a sessão em {:?}", path);
+        Ok(Some(session))
+    }
+
+    pub fn get_pipeline(&self) -> anyhow::Result<PipelineConfig> {
+        match &self.pipeline {
+            Some(path) => PipelineConfig::load(path),
+            None => Ok(PipelineConfig::default()),
+        }
+    }
+
+    pub fn get_breakpoints(&self) -> anyhow::Result<Breakpoints> {
+        let compile = |rules: &[String]| -> anyhow::Result<Vec<Regex>> {
+            rules.iter().map(|r| Ok(Regex::new(r)?)).collect()
+        };
+
+        Ok(Breakpoints::new(
+            compile(&self.break_request)?,
+            compile(&self.break_response)?,
+            Duration::from_secs(self.break_timeout),
+        ))
+    }
 }
 
 pub fn load() -> (Config, AllowList) {
     let mut config = Config::parse();
 
     let mut allow_list = config.allow_list.clone();
+    if config.command.is_some() {
+        return (config, AllowList::new(allow_list));
+    }
     
     let mut urls: Vec<String> = vec![];
 
@@ -4,7 +4,7 @@ use tokio::{sync::mpsc::Receiver, task::JoinHandle};
 pub async fn start_consumer(mut rx: Receiver<InterceptedResponse>, dispatcher: Dispatcher) -> JoinHandle<()> {
     tokio::spawn(async move {
         while let Some(resp) = rx.recv().await {
-            dispatcher.emit(StageId::Filter, resp);
+            dispatcher.emit(StageId::Filter, resp).await;
         }
 
         println!("✅ Fila fechada. Consumer parando.");
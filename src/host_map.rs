@@ -0,0 +1,212 @@
+use std::{
+    fs,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+#[derive(Debug, Clone)]
+pub struct HostMapping {
+    pattern: String,
+    ip: IpAddr,
+    port: Option<u16>,
+}
+
+impl HostMapping {
+    pub fn parse(entry: &str) -> anyhow::Result<Self> {
+        let (pattern, target) = entry
+            .split_once('=')
+            .ok_or_else(|| anyhow!("mapeamento inválido '{entry}', esperado host=ip[:porta]"))?;
+
+        Self::new(pattern, target)
+    }
+
+    fn new(pattern: &str, target: &str) -> anyhow::Result<Self> {
+        let pattern = pattern.trim().trim_end_matches('.').to_ascii_lowercase();
+        if pattern.is_empty() {
+            bail!("mapeamento sem host para o destino '{target}'");
+        }
+
+        let target = target.trim();
+        let (ip, port) = if let Ok(addr) = target.parse::<SocketAddr>() {
+            (addr.ip(), Some(addr.port()))
+        } else if let Ok(ip) = target.trim_matches(['[', ']']).parse::<IpAddr>() {
+            (ip, None)
+        } else {
+            bail!("destino inválido '{target}' para {pattern}, esperado ip[:porta]");
+        };
+
+        Ok(Self { pattern, ip, port })
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self.pattern.strip_prefix("*.") {
+            Some(suffix) => host
+                .strip_suffix(suffix)
+                .is_some_and(|rest| rest.len() > 1 && rest.ends_with('.')),
+            None => self.pattern == host,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HostMap {
+    entries: Arc<Vec<HostMapping>>,
+}
+
+impl HostMap {
+    pub fn new(mut entries: Vec<HostMapping>) -> Self {
+        // exact names first, then the most specific wildcard wins
+        entries.sort_by_key(|e| {
+            (
+                e.pattern.starts_with("*."),
+                std::cmp::Reverse(e.pattern.len()),
+            )
+        });
+
+        Self {
+            entries: Arc::new(entries),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn lookup(&self, host: &str) -> Option<(IpAddr, Option<u16>)> {
+        let host = host
+            .trim_matches(['[', ']'])
+            .trim_end_matches('.')
+            .to_ascii_lowercase();
+
+        self.entries
+            .iter()
+            .find(|e| e.matches(&host))
+            .map(|e| (e.ip, e.port))
+    }
+
+    pub fn resolve(&self, host: &str, port: u16) -> (String, u16) {
+        match self.lookup(host) {
+            Some((ip, mapped_port)) => (ip.to_string(), mapped_port.unwrap_or(port)),
+            None => (host.trim_matches(['[', ']']).to_string(), port),
+        }
+    }
+}
+
+pub fn parse_hosts_file(path: &Path) -> anyhow::Result<Vec<HostMapping>> {
+    parse_hosts(&fs::read_to_string(path)?)
+}
+
+pub fn parse_hosts(content: &str) -> anyhow::Result<Vec<HostMapping>> {
+    let mut entries = vec![];
+
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let target = fields.next().unwrap_or_default();
+        for host in fields {
+            entries.push(HostMapping::new(host, target)?);
+        }
+    }
+
+    Ok(entries)
+}
+
+impl Resolve for HostMap {
+    fn resolve(&self, name: Name) -> Resolving {
+        let mapped = self.lookup(name.as_str());
+        let host = name.as_str().to_string();
+
+        Box::pin(async move {
+            if let Some((ip, port)) = mapped {
+                let addrs: Addrs = Box::new(std::iter::once(SocketAddr::new(ip, port.unwrap_or(0))));
+                return Ok(addrs);
+            }
+
+            let addrs: Addrs = Box::new(tokio::net::lookup_host((host, 0)).await?);
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(entries: &[&str]) -> HostMap {
+        HostMap::new(entries.iter().map(|e| HostMapping::parse(e).unwrap()).collect())
+    }
+
+    #[test]
+    fn parses_targets_with_and_without_port() {
+        let m = HostMapping::parse("Api.Example.com.=10.0.0.1:8443").unwrap();
+        assert_eq!(m.pattern, "api.example.com");
+        assert_eq!(m.ip, "10.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!(m.port, Some(8443));
+
+        let m = HostMapping::parse("example.com=[::1]").unwrap();
+        assert_eq!(m.ip, "::1".parse::<IpAddr>().unwrap());
+        assert_eq!(m.port, None);
+
+        let m = HostMapping::parse("example.com=[::1]:443").unwrap();
+        assert_eq!(m.port, Some(443));
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        assert!(HostMapping::parse("example.com").is_err());
+        assert!(HostMapping::parse("=10.0.0.1").is_err());
+        assert!(HostMapping::parse("example.com=not-an-ip").is_err());
+    }
+
+    #[test]
+    fn wildcard_matches_subdomains_only() {
+        let m = map(&["*.example.com=10.0.0.1"]);
+        assert!(m.lookup("api.example.com").is_some());
+        assert!(m.lookup("a.b.example.com").is_some());
+        assert!(m.lookup("API.Example.COM.").is_some());
+        assert!(m.lookup("example.com").is_none());
+        assert!(m.lookup("badexample.com").is_none());
+        assert!(m.lookup(".example.com").is_none());
+    }
+
+    #[test]
+    fn exact_and_specific_entries_win() {
+        let m = map(&[
+            "*.example.com=10.0.0.1",
+            "*.api.example.com=10.0.0.2",
+            "v1.api.example.com=10.0.0.3:8080",
+        ]);
+
+        assert_eq!(m.resolve("v1.api.example.com", 443), ("10.0.0.3".into(), 8080));
+        assert_eq!(m.resolve("v2.api.example.com", 443), ("10.0.0.2".into(), 443));
+        assert_eq!(m.resolve("www.example.com", 443), ("10.0.0.1".into(), 443));
+        assert_eq!(m.resolve("other.org", 80), ("other.org".into(), 80));
+        assert_eq!(m.resolve("[::1]", 80), ("::1".into(), 80));
+    }
+
+    #[test]
+    fn parses_hosts_file_format() {
+        let entries = parse_hosts(
+            "# comment\n\
+             127.0.0.1 localhost local.test   # trailing\n\
+             \n\
+             ::1\tip6-localhost\n\
+             10.0.0.9\n",
+        )
+        .unwrap();
+
+        let m = HostMap::new(entries);
+        assert_eq!(m.lookup("local.test"), Some(("127.0.0.1".parse().unwrap(), None)));
+        assert_eq!(m.lookup("ip6-localhost"), Some(("::1".parse().unwrap(), None)));
+        assert!(m.lookup("10.0.0.9").is_none());
+        assert!(parse_hosts("bogus host.test").is_err());
+    }
+}
@@ -0,0 +1,39 @@
+// the library surface is the proxy, the analyzer and the navigator; everything else backs the binary
+pub mod analyzer;
+#[cfg(feature = "navigator")]
+pub mod navigator;
+pub mod proxy;
+
+pub(crate) mod allow_list;
+pub(crate) mod app;
+pub(crate) mod ca;
+pub(crate) mod config;
+pub(crate) mod consumer;
+pub(crate) mod host_map;
+pub(crate) mod recon;
+pub(crate) mod repeater;
+pub(crate) mod session;
+
+pub use allow_list::AllowList;
+pub use analyzer::{
+    cpu::{CpuLimits, CpuPool},
+    event::Dispatcher,
+    finding::{Confidence, Finding},
+    intercepted::InterceptedResponse,
+    pipeline::PipelineConfig,
+    router::{DispatchOptions, StageRegistry},
+    sink::FindingSink,
+    stage::{Stage, StageId},
+    stages::js::analyzer::run_js_analysis,
+};
+pub use consumer::start_consumer;
+pub use host_map::{HostMap, HostMapping};
+pub use proxy::{ProxyContext, start_proxy};
+pub use recon::{CertificateRecord, Recon};
+pub use session::Session;
+
+// what the mboitata binary runs, so the argument types stay internal
+pub async fn run_cli() -> anyhow::Result<()> {
+    let (config, allow_list) = config::load();
+    app::run(config, allow_list).await
+}
@@ -1,15 +1,18 @@
 use headless_chrome::{Browser, LaunchOptionsBuilder, Tab};
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use tokio::{
     sync::broadcast,
     task::{self, JoinError}, time::Instant,
 };
 
+pub type Visited = Arc<dyn Fn(&str) + Send + Sync>;
+
 pub async fn run(
     urls: Vec<String>,
     workers: usize,
     proxy: String,
     kill_sign: broadcast::Sender<()>,
+    on_visited: Visited,
 ) {
     println!("🕐 Esperando proxy ficar pronto...");
     tokio::time::sleep(std::time::Duration::from_secs(5)).await;
@@ -22,7 +25,7 @@ pub async fn run(
     let mut handles = vec![];
 
     for (id, urls_chunk) in chunked.into_iter().enumerate() {
-        let handle = navigate_block(urls_chunk, id + 1, proxy.clone());
+        let handle = navigate_block(urls_chunk, id + 1, proxy.clone(), on_visited.clone());
         handles.push(handle);
     }
 
@@ -34,7 +37,12 @@ pub async fn run(
     let _ = kill_sign.send(());
 }
 
-async fn navigate_block(urls: Vec<String>, id: usize, proxy: String) -> Result<(), JoinError> {
+async fn navigate_block(
+    urls: Vec<String>,
+    id: usize,
+    proxy: String,
+    on_visited: Visited,
+) -> Result<(), JoinError> {
     task::spawn_blocking(move || {
         println!("🔥 Mboîtatá worker {id} acendeu sua tocha");
 
@@ -81,6 +89,7 @@ async fn navigate_block(urls: Vec<String>, id: usize, proxy: String) -> Result<(
 
             println!("✅ Worker {id} página carregada: {url}");
             std::thread::sleep(Duration::from_secs(3));
+            on_visited(&url);
         }
 
         println!("✅ Worker {id} apagou sua chama com sucesso.");
@@ -0,0 +1,390 @@
+use std::{
+    collections::BTreeMap,
+    io::Read,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::bail;
+use http_body_util::{BodyExt, combinators::BoxBody};
+use hyper::{
+    HeaderMap, Method, Request, Response, StatusCode, Uri, Version,
+    body::{Bytes, Incoming},
+    header::{self, HeaderName, HeaderValue},
+    service::service_fn,
+};
+use hyper_util::rt::TokioIo;
+use regex::Regex;
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, oneshot},
+};
+
+use crate::{
+    analyzer::body::MAX_DECODED_SIZE,
+    proxy::{ServerBuilder, create_response, full},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Request,
+    Response,
+}
+
+#[derive(Debug)]
+pub enum Decision {
+    Forward,
+    Replace(Bytes),
+    Drop,
+}
+
+#[derive(Debug)]
+struct Pending {
+    phase: Phase,
+    url: String,
+    raw: Bytes,
+    reply: oneshot::Sender<Decision>,
+}
+
+pub struct ResponseParts {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct Breakpoints {
+    request_rules: Arc<Vec<Regex>>,
+    response_rules: Arc<Vec<Regex>>,
+    timeout: Duration,
+    pending: Arc<Mutex<BTreeMap<u64, Pending>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Breakpoints {
+    pub fn new(request_rules: Vec<Regex>, response_rules: Vec<Regex>, timeout: Duration) -> Self {
+        Self {
+            request_rules: Arc::new(request_rules),
+            response_rules: Arc::new(response_rules),
+            timeout,
+            pending: Arc::new(Mutex::new(BTreeMap::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.request_rules.is_empty() && self.response_rules.is_empty()
+    }
+
+    async fn pause(&self, phase: Phase, url: &str, raw: Bytes) -> Decision {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (reply, decision) = oneshot::channel();
+
+        self.pending.lock().unwrap().insert(
+            id,
+            Pending {
+                phase,
+                url: url.to_string(),
+                raw,
+                reply,
+            },
+        );
+        println!("⏸️ [Breakpoint #{id}] {:?} pausado: {url}", phase);
+
+        match tokio::time::timeout(self.timeout, decision).await {
+            Ok(Ok(decision)) => decision,
+            _ => {
+                self.pending.lock().unwrap().remove(&id);
+                println!("⏱️ [Breakpoint #{id}] Sem decisão, encaminhando sem alterações.");
+                Decision::Forward
+            }
+        }
+    }
+
+    pub async fn intercept_request(
+        &self,
+        url: &str,
+        req: Request<Incoming>,
+    ) -> Result<Option<Request<BoxBody<Bytes, hyper::Error>>>, hyper::Error> {
+        if !self.request_rules.iter().any(|r| r.is_match(url)) {
+            return Ok(Some(req.map(|b| b.boxed())));
+        }
+
+        let (parts, body) = req.into_parts();
+        let body = body.collect().await?.to_bytes();
+        let raw = render_message(
+            format!("{} {} {:?}", parts.method, parts.uri, parts.version),
+            &parts.headers,
+            &body,
+        );
+
+        match self.pause(Phase::Request, url, raw).await {
+            Decision::Forward => Ok(Some(Request::from_parts(parts, full(body)))),
+            Decision::Drop => Ok(None),
+            Decision::Replace(raw) => match parse_request(&raw) {
+                Ok((method, uri, headers, body)) => {
+                    let mut req = Request::new(full(body));
+                    *req.method_mut() = method;
+                    *req.uri_mut() = uri;
+                    *req.version_mut() = parts.version;
+                    *req.headers_mut() = headers;
+                    Ok(Some(req))
+                }
+                Err(e) => {
+                    eprintln!("❌ Requisição editada inválida ({e}), encaminhando a original.");
+                    Ok(Some(Request::from_parts(parts, full(body))))
+                }
+            },
+        }
+    }
+
+    pub async fn intercept_response(
+        &self,
+        url: &str,
+        version: Version,
+        original: ResponseParts,
+    ) -> Option<ResponseParts> {
+        if !self.response_rules.iter().any(|r| r.is_match(url)) {
+            return Some(original);
+        }
+
+        let mut shown_headers = original.headers.clone();
+        let shown_body = match decode(&original.headers, &original.body, MAX_DECODED_SIZE) {
+            Some(decoded) => {
+                shown_headers.remove(header::CONTENT_ENCODING);
+                decoded
+            }
+            None => original.body.to_vec(),
+        };
+        let raw = render_message(
+            format!("{:?} {}", version, original.status),
+            &shown_headers,
+            &shown_body,
+        );
+
+        match self.pause(Phase::Response, url, raw).await {
+            Decision::Forward => Some(original),
+            Decision::Drop => None,
+            Decision::Replace(raw) => match parse_response(&raw) {
+                Ok(edited) => Some(edited),
+                Err(e) => {
+                    eprintln!("❌ Resposta editada inválida ({e}), encaminhando a original.");
+                    Some(original)
+                }
+            },
+        }
+    }
+
+    fn decide(&self, id: u64, decision: Decision) -> bool {
+        match self.pending.lock().unwrap().remove(&id) {
+            Some(pending) => pending.reply.send(decision).is_ok(),
+            None => false,
+        }
+    }
+
+    pub async fn serve_api(
+        self,
+        addr: String,
+        mut kill_signal: broadcast::Receiver<()>,
+    ) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(&addr).await?;
+        println!("🛑 API de breakpoints ouvindo em http://{addr}");
+
+        loop {
+            tokio::select! {
+                _ = kill_signal.recv() => return Ok(()),
+                res = listener.accept() => {
+                    let (stream, _) = match res {
+                        Ok(s) => s,
+                        Err(err) => {
+                            eprintln!("Erro ao aceitar conexão: {:?}", err);
+                            continue;
+                        }
+                    };
+
+                    let this = self.clone();
+                    let service = service_fn(move |req| {
+                        let this = this.clone();
+                        async move { this.handle_api(req).await }
+                    });
+
+                    tokio::task::spawn(async move {
+                        if let Err(err) = ServerBuilder::new()
+                            .serve_connection(TokioIo::new(stream), service)
+                            .await
+                        {
+                            eprintln!("Failed to serve breakpoint API: {:?}", err);
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    async fn handle_api(
+        &self,
+        req: Request<Incoming>,
+    ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
+        let method = req.method().clone();
+        let segments: Vec<String> = req
+            .uri()
+            .path()
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        let id = segments.get(1).and_then(|s| s.parse::<u64>().ok());
+
+        let resp = match (method, segments.as_slice(), id) {
+            (Method::GET, ["pending"], _) => {
+                let list = self
+                    .pending
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|(id, p)| format!("{id}\t{:?}\t{}", p.phase, p.url))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Response::new(full(list))
+            }
+            (Method::GET, ["pending", _], Some(id)) => {
+                match self.pending.lock().unwrap().get(&id) {
+                    Some(p) => Response::new(full(p.raw.clone())),
+                    None => not_found(id),
+                }
+            }
+            (Method::POST, ["pending", _, action], Some(id)) => {
+                let decision = match *action {
+                    "forward" => Decision::Forward,
+                    "drop" => Decision::Drop,
+                    "edit" => Decision::Replace(req.into_body().collect().await?.to_bytes()),
+                    _ => return Ok(create_response("ação inválida".into(), StatusCode::BAD_REQUEST)),
+                };
+
+                if self.decide(id, decision) {
+                    Response::new(full("ok"))
+                } else {
+                    not_found(id)
+                }
+            }
+            _ => create_response(
+                "GET /pending | GET /pending/{id} | POST /pending/{id}/(forward|edit|drop)".into(),
+                StatusCode::NOT_FOUND,
+            ),
+        };
+
+        Ok(resp)
+    }
+}
+
+pub fn dropped_response() -> Response<BoxBody<Bytes, hyper::Error>> {
+    create_response("Descartado pelo breakpoint".into(), StatusCode::BAD_GATEWAY)
+}
+
+fn not_found(id: u64) -> Response<BoxBody<Bytes, hyper::Error>> {
+    create_response(format!("breakpoint {id} não encontrado"), StatusCode::NOT_FOUND)
+}
+
+fn render_message(start_line: String, headers: &HeaderMap, body: &[u8]) -> Bytes {
+    let mut raw = start_line.into_bytes();
+    raw.extend_from_slice(b"\r\n");
+    for (k, v) in headers {
+        raw.extend_from_slice(k.as_str().as_bytes());
+        raw.extend_from_slice(b": ");
+        raw.extend_from_slice(v.as_bytes());
+        raw.extend_from_slice(b"\r\n");
+    }
+    raw.extend_from_slice(b"\r\n");
+    raw.extend_from_slice(body);
+    Bytes::from(raw)
+}
+
+// same cap as the analyzer; a body that decodes past it is shown as it came
+fn decode(headers: &HeaderMap, body: &[u8], limit: usize) -> Option<Vec<u8>> {
+    let encoding = headers.get(header::CONTENT_ENCODING)?.to_str().ok()?;
+    let decoder: Box<dyn Read + '_> = match encoding {
+        "gzip" => Box::new(flate2::read::GzDecoder::new(body)),
+        "br" => Box::new(brotli::Decompressor::new(body, 4096)),
+        _ => return None,
+    };
+
+    let mut out = vec![];
+    decoder.take(limit as u64 + 1).read_to_end(&mut out).ok()?;
+    (out.len() <= limit).then_some(out)
+}
+
+fn collect_headers(parsed: &[httparse::Header], body_len: usize) -> anyhow::Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for h in parsed {
+        headers.append(
+            HeaderName::from_bytes(h.name.as_bytes())?,
+            HeaderValue::from_bytes(h.value)?,
+        );
+    }
+
+    headers.remove(header::TRANSFER_ENCODING);
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body_len));
+    Ok(headers)
+}
+
+fn parse_request(raw: &[u8]) -> anyhow::Result<(Method, Uri, HeaderMap, Bytes)> {
+    let mut parsed = [httparse::EMPTY_HEADER; 128];
+    let mut req = httparse::Request::new(&mut parsed);
+
+    let offset = match req.parse(raw)? {
+        httparse::Status::Complete(n) => n,
+        httparse::Status::Partial => bail!("mensagem incompleta"),
+    };
+
+    let method = Method::from_bytes(req.method.unwrap_or("GET").as_bytes())?;
+    let uri = req.path.unwrap_or("/").parse::<Uri>()?;
+    let body = Bytes::copy_from_slice(&raw[offset..]);
+    let headers = collect_headers(req.headers, body.len())?;
+
+    Ok((method, uri, headers, body))
+}
+
+fn parse_response(raw: &[u8]) -> anyhow::Result<ResponseParts> {
+    let mut parsed = [httparse::EMPTY_HEADER; 128];
+    let mut resp = httparse::Response::new(&mut parsed);
+
+    let offset = match resp.parse(raw)? {
+        httparse::Status::Complete(n) => n,
+        httparse::Status::Partial => bail!("mensagem incompleta"),
+    };
+
+    let status = StatusCode::from_u16(resp.code.unwrap_or(200))?;
+    let body = Bytes::copy_from_slice(&raw[offset..]);
+    let headers = collect_headers(resp.headers, body.len())?;
+
+    Ok(ResponseParts {
+        status,
+        headers,
+        body,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn bodies_that_decode_past_the_cap_are_left_as_they_came() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(&[b'a'; 10_000]).unwrap();
+        let body = encoder.finish().unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+
+        assert_eq!(decode(&headers, &body, 10_000).map(|d| d.len()), Some(10_000));
+        assert_eq!(decode(&headers, &body, 1_000), None);
+    }
+}
@@ -1,19 +1,70 @@
+use clap::ValueEnum;
+use lru::LruCache;
 use pki_types::pem::{PemObject, SectionKind};
 use rcgen::string::Ia5String;
 use rcgen::{
-    CertificateParams, DistinguishedName, DnType, IsCa, Issuer, KeyPair, KeyUsagePurpose, SanType,
+    CertificateParams, CidrSubnet, DistinguishedName, DnType, GeneralSubtree, IsCa, Issuer, KeyPair,
+    KeyUsagePurpose, NameConstraints, PublicKeyData, SanType,
 };
-use rustls::ServerConfig;
-use rustls::pki_types::PrivateKeyDer;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::RwLock;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, ServerConfig, SignatureScheme};
+use sanitize_filename::sanitize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use time::{Duration as TimeDuration, OffsetDateTime};
+use tokio_rustls::TlsConnector;
+use x509_parser::{
+    extensions::GeneralName,
+    prelude::{FromDer, X509Certificate},
+};
+
+use crate::{
+    analyzer::intercepted::ConnectionInfo,
+    host_map::HostMap,
+    proxy::{connect_upstream, split_host_port},
+    recon::ip_from_bytes,
+};
+
+pub const CA_CERT_FILE: &str = "ca-cert.pem";
+pub const CA_KEY_FILE: &str = "ca-key.pem";
+pub const LEAF_KEY_FILE: &str = "leaf-key.pem";
+pub const LEAF_DIR: &str = "leaf";
+pub const MIRRORED_DIR: &str = "mirrored";
+const DEFAULT_CACHE_SIZE: usize = 1000;
+
+type CertCache = Arc<Mutex<LruCache<(String, bool), Arc<ServerConfig>>>>;
 
-type CertCache = Arc<RwLock<HashMap<String, Arc<ServerConfig>>>>;
+// forged and mirrored leafs of the same name are kept apart, in memory and on disk
+#[derive(Debug, Clone)]
+struct Leaf {
+    name: String,
+    mirrored: bool,
+}
+
+impl Leaf {
+    fn cache_key(&self) -> (String, bool) {
+        (self.name.clone(), self.mirrored)
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct CertificateManager {
     issuer: Arc<Issuer<'static, KeyPair>>,
+    ca_der: CertificateDer<'static>,
+    scope: NameScope,
+    leaf_key: Arc<KeyPair>,
+    leaf_dir: Option<PathBuf>,
     cert_cache: CertCache,
+    wildcards: bool,
+    mirror: Option<HostMap>,
 }
 
 impl CertificateManager {
@@ -21,53 +72,498 @@ impl CertificateManager {
         let key = KeyPair::from_pem(ca_key_pem)?;
         let issuer = Issuer::from_ca_cert_pem(ca_cert_pem, key)?;
 
+        let ca_der = CertificateDer::from_pem_slice(ca_cert_pem.as_bytes())?;
+        let (_, ca_cert) = X509Certificate::from_der(&ca_der)
+            .map_err(|e| anyhow::anyhow!("certificado da CA inválido: {e}"))?;
+
+        let scope = NameScope::from_ca(&ca_cert);
+
         Ok(Self {
             issuer: Arc::new(issuer),
-            cert_cache: Arc::new(RwLock::new(HashMap::new())),
+            ca_der,
+            scope,
+            leaf_key: Arc::new(KeyPair::generate()?),
+            leaf_dir: None,
+            cert_cache: Arc::new(Mutex::new(LruCache::new(
+                NonZeroUsize::new(DEFAULT_CACHE_SIZE).unwrap(),
+            ))),
+            wildcards: false,
+            mirror: None,
         })
     }
 
-    pub async fn get_server_config(&self, hostname: &str) -> anyhow::Result<Arc<ServerConfig>> {
-        let hostname = normalize_hostname(hostname);
-        if let Some(cfg) = self.cert_cache.read().await.get(hostname.as_str()) {
-            return Ok(cfg.clone());
+    pub fn with_wildcards(mut self, enabled: bool) -> Self {
+        self.wildcards = enabled;
+        self
+    }
+
+    pub fn with_upstream_mirror(mut self, host_map: Option<HostMap>) -> Self {
+        self.mirror = host_map;
+        self
+    }
+
+    pub fn with_cache_size(mut self, size: usize) -> Self {
+        let size = NonZeroUsize::new(size).unwrap_or(NonZeroUsize::MIN);
+        self.cert_cache = Arc::new(Mutex::new(LruCache::new(size)));
+        self
+    }
+
+    pub fn with_storage(mut self, certs_dir: &Path) -> anyhow::Result<Self> {
+        let key_path = certs_dir.join(LEAF_KEY_FILE);
+        let leaf_key = if key_path.exists() {
+            KeyPair::from_pem(&fs::read_to_string(&key_path)?)?
+        } else {
+            let key = KeyPair::generate()?;
+            write_private_key(&key_path, key.serialize_pem())?;
+            key
+        };
+
+        let leaf_dir = certs_dir.join(LEAF_DIR);
+        fs::create_dir_all(&leaf_dir)?;
+
+        self.leaf_key = Arc::new(leaf_key);
+        self.leaf_dir = Some(leaf_dir);
+        Ok(self)
+    }
+
+    pub async fn prewarm(&self, hosts: &[String]) {
+        let mut warmed = 0;
+        for host in hosts {
+            match self.get_server_config(host).await {
+                Ok(_) => warmed += 1,
+                Err(e) => eprintln!("⚠️ Não foi possível preparar o certificado de {host}: {e}"),
+            }
         }
+        println!("[INFO] {warmed} certificado(s) preparados para a allowlist.");
+    }
 
-        let server_config = Arc::new(self.generate_certificate_for_host(hostname.as_str())?);
+    pub async fn get_server_config(&self, addr: &str) -> anyhow::Result<Arc<ServerConfig>> {
+        let hostname = normalize_hostname(addr);
+        if !self.scope.permits(&hostname) {
+            anyhow::bail!("{hostname} está fora das name constraints da CA");
+        }
+
+        if let Some(host_map) = &self.mirror {
+            let leaf = Leaf {
+                name: hostname.clone(),
+                mirrored: true,
+            };
+            if let Some(cfg) = self.cached(&leaf)? {
+                return Ok(cfg);
+            }
+
+            // a failed fetch falls back to a forged leaf for now, the mirror is tried again next time
+            let port = split_host_port(addr).map(|(_, p)| p).unwrap_or(443);
+            match mirror_upstream_params(host_map, &hostname, port).await {
+                Ok(params) => return self.issue(leaf, params),
+                Err(e) => eprintln!("⚠️ Não foi possível espelhar o certificado de {hostname}: {e}"),
+            }
+        }
+
+        let name = self.certificate_name(&hostname);
+        let leaf = Leaf {
+            name: name.clone(),
+            mirrored: false,
+        };
+        if let Some(cfg) = self.cached(&leaf)? {
+            return Ok(cfg);
+        }
+        self.issue(leaf, forged_params(&name)?)
+    }
+
+    fn cached(&self, leaf: &Leaf) -> anyhow::Result<Option<Arc<ServerConfig>>> {
+        if let Some(cfg) = self.cert_cache.lock().unwrap().get(&leaf.cache_key()) {
+            return Ok(Some(cfg.clone()));
+        }
 
+        let Some(cert) = self.load_persisted(leaf) else {
+            return Ok(None);
+        };
+        let server_config = Arc::new(self.server_config(cert)?);
         self.cert_cache
-            .write()
-            .await
-            .insert(hostname.to_string(), server_config.clone());
+            .lock()
+            .unwrap()
+            .put(leaf.cache_key(), server_config.clone());
+        Ok(Some(server_config))
+    }
+
+    fn issue(&self, leaf: Leaf, mut params: CertificateParams) -> anyhow::Result<Arc<ServerConfig>> {
+        params
+            .subject_alt_names
+            .retain(|san| self.scope.permits_san(san));
+        let cert = params.signed_by(self.leaf_key.as_ref(), &self.issuer)?;
+        self.persist(&leaf, &cert.pem());
+
+        let server_config = Arc::new(self.server_config(cert.der().clone())?);
+        self.cert_cache
+            .lock()
+            .unwrap()
+            .put(leaf.cache_key(), server_config.clone());
 
         Ok(server_config)
     }
 
-    fn generate_certificate_for_host(&self, hostname: &str) -> anyhow::Result<ServerConfig> {
-        let mut params = CertificateParams::new(vec![hostname.to_string()]).unwrap();
+    fn certificate_name(&self, hostname: &str) -> String {
+        if !self.wildcards || hostname.parse::<IpAddr>().is_ok() {
+            return hostname.to_string();
+        }
 
-        let mut dn = DistinguishedName::new();
-        dn.push(DnType::CommonName, hostname);
-        dn.push(DnType::OrganizationName, "Mboi Tata Proxy");
-        params.distinguished_name = dn;
+        // one wildcard per parent domain, but never for a bare "*.tld"
+        match hostname.split_once('.') {
+            Some((_, parent)) if parent.contains('.') && self.scope.permits(parent) => {
+                format!("*.{parent}")
+            }
+            _ => hostname.to_string(),
+        }
+    }
 
-        params.subject_alt_names = vec![SanType::DnsName(Ia5String::try_from(hostname)?)];
+    // sanitizing alone maps different names to one file (`1::1` and `11::`), the hash keeps them apart
+    fn leaf_path(&self, leaf: &Leaf) -> Option<PathBuf> {
+        let mut dir = self.leaf_dir.clone()?;
+        if leaf.mirrored {
+            dir = dir.join(MIRRORED_DIR);
+        }
 
-        let key_pair = KeyPair::generate()?;
-        let cert = params.signed_by(&key_pair, &self.issuer)?;
+        let hash = hex::encode(Sha256::digest(leaf.name.as_bytes()));
+        Some(dir.join(format!("{}-{}.pem", sanitize(leaf.name.replace('*', "_")), &hash[..16])))
+    }
 
+    fn load_persisted(&self, leaf: &Leaf) -> Option<CertificateDer<'static>> {
+        let name = leaf.name.as_str();
+        let path = self.leaf_path(leaf)?;
+        let pem = fs::read(&path).ok()?;
+        let der = CertificateDer::from_pem_slice(&pem).ok()?;
+
+        let (_, cert) = X509Certificate::from_der(&der).ok()?;
+        if !cert.validity().is_valid() {
+            return None;
+        }
+        if !covers(&cert, name) {
+            println!("[INFO] Certificado salvo de {name} não cobre esse nome, gerando outro.");
+            return None;
+        }
+
+        // a CA regenerated with the same subject, or a new shared leaf key, would serve a broken chain
+        let (_, ca_cert) = X509Certificate::from_der(&self.ca_der).ok()?;
+        if cert.verify_signature(Some(ca_cert.public_key())).is_err() {
+            println!("[INFO] Certificado salvo de {name} não foi assinado pela CA atual, gerando outro.");
+            return None;
+        }
+        if cert.public_key().raw != self.leaf_key.subject_public_key_info() {
+            println!("[INFO] Certificado salvo de {name} usa outra chave, gerando outro.");
+            return None;
+        }
+
+        Some(der)
+    }
+
+    fn persist(&self, leaf: &Leaf, pem: &str) {
+        let Some(path) = self.leaf_path(leaf) else {
+            return;
+        };
+
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(e) = fs::write(&path, pem) {
+            eprintln!("Erro ao salvar o arquivo {:?}: {}", path, e);
+        }
+    }
+
+    fn server_config(&self, cert: CertificateDer<'static>) -> anyhow::Result<ServerConfig> {
         let private_key =
-            PrivateKeyDer::from_pem(SectionKind::PrivateKey, key_pair.serialize_der()).unwrap();
+            PrivateKeyDer::from_pem(SectionKind::PrivateKey, self.leaf_key.serialize_der()).unwrap();
 
         let config = ServerConfig::builder()
             .with_no_client_auth()
-            .with_single_cert(vec![cert.der().clone()], private_key)?;
+            .with_single_cert(vec![cert], private_key)?;
 
         Ok(config)
     }
 }
 
-pub fn create_ca_certificate() -> anyhow::Result<(String, String)> {
+pub fn write_private_key(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    std::io::Write::write_all(&mut options.open(path)?, contents.as_ref())
+}
+
+#[derive(Debug, Clone, Default)]
+struct NameScope {
+    dns: Vec<String>,
+    ips: Vec<(Vec<u8>, Vec<u8>)>,
+    excluded_ips: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl NameScope {
+    fn from_ca(ca_cert: &X509Certificate) -> Self {
+        let mut scope = Self::default();
+        let Ok(Some(ext)) = ca_cert.name_constraints() else {
+            return scope;
+        };
+
+        for subtree in ext.value.permitted_subtrees.iter().flatten() {
+            match &subtree.base {
+                GeneralName::DNSName(dns) => scope.dns.push(dns.to_ascii_lowercase()),
+                GeneralName::IPAddress(bytes) => scope.ips.push(ip_subtree(bytes)),
+                _ => {}
+            }
+        }
+        for subtree in ext.value.excluded_subtrees.iter().flatten() {
+            if let GeneralName::IPAddress(bytes) = &subtree.base {
+                scope.excluded_ips.push(ip_subtree(bytes));
+            }
+        }
+
+        scope
+    }
+
+    // RFC 5280: each name type is only constrained when the CA lists subtrees of that type
+    fn permits(&self, hostname: &str) -> bool {
+        match hostname.parse::<IpAddr>() {
+            Ok(ip) => self.permits_ip(ip),
+            Err(_) => {
+                let name = hostname.trim_start_matches("*.");
+                self.dns.is_empty()
+                    || self.dns.iter().any(|c| match c.strip_prefix('.') {
+                        Some(_) => name.ends_with(c.as_str()),
+                        None => name == c || name.ends_with(&format!(".{c}")),
+                    })
+            }
+        }
+    }
+
+    fn permits_ip(&self, ip: IpAddr) -> bool {
+        let octets = match ip {
+            IpAddr::V4(v4) => v4.octets().to_vec(),
+            IpAddr::V6(v6) => v6.octets().to_vec(),
+        };
+        let within = |(addr, mask): &(Vec<u8>, Vec<u8>)| {
+            addr.len() == octets.len()
+                && octets
+                    .iter()
+                    .zip(addr.iter().zip(mask))
+                    .all(|(o, (a, m))| o & m == a & m)
+        };
+
+        !self.excluded_ips.iter().any(within) && (self.ips.is_empty() || self.ips.iter().any(within))
+    }
+
+    fn permits_san(&self, san: &SanType) -> bool {
+        match san {
+            SanType::DnsName(dns) => self.permits(dns.as_str()),
+            SanType::IpAddress(ip) => self.permits_ip(*ip),
+            _ => true,
+        }
+    }
+}
+
+fn ip_subtree(bytes: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let (addr, mask) = bytes.split_at(bytes.len() / 2);
+    (addr.to_vec(), mask.to_vec())
+}
+
+fn covers(cert: &X509Certificate, name: &str) -> bool {
+    let Ok(Some(ext)) = cert.subject_alternative_name() else {
+        return false;
+    };
+
+    let ip = name.parse::<IpAddr>().ok();
+    ext.value.general_names.iter().any(|san| match (san, ip) {
+        (GeneralName::DNSName(dns), None) => dns.eq_ignore_ascii_case(name),
+        (GeneralName::IPAddress(bytes), Some(ip)) => ip_from_bytes(bytes) == Some(ip),
+        _ => false,
+    })
+}
+
+fn forged_params(hostname: &str) -> anyhow::Result<CertificateParams> {
+    let mut params = CertificateParams::default();
+
+    let mut dn = DistinguishedName::new();
+    dn.push(DnType::CommonName, hostname);
+    dn.push(DnType::OrganizationName, "Mboi Tata Proxy");
+    params.distinguished_name = dn;
+
+    params.subject_alt_names = vec![san_for(hostname)?];
+
+    Ok(params)
+}
+
+fn san_for(hostname: &str) -> anyhow::Result<SanType> {
+    Ok(match hostname.parse::<IpAddr>() {
+        Ok(ip) => SanType::IpAddress(ip),
+        Err(_) => SanType::DnsName(Ia5String::try_from(hostname)?),
+    })
+}
+
+async fn mirror_upstream_params(
+    host_map: &HostMap,
+    hostname: &str,
+    port: u16,
+) -> anyhow::Result<CertificateParams> {
+    let der = tokio::time::timeout(
+        Duration::from_secs(10),
+        fetch_upstream_certificate(host_map, hostname, port),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("tempo esgotado"))??;
+
+    let (_, cert) = X509Certificate::from_der(&der)
+        .map_err(|e| anyhow::anyhow!("certificado inválido: {e}"))?;
+
+    let mut params = CertificateParams::default();
+
+    let mut dn = DistinguishedName::new();
+    for attr in cert.subject().iter_attributes() {
+        let (Some(oid), Ok(value)) = (attr.attr_type().iter(), attr.as_str()) else {
+            continue;
+        };
+        dn.push(DnType::from_oid(&oid.collect::<Vec<_>>()), value);
+    }
+    params.distinguished_name = dn;
+
+    if let Ok(Some(ext)) = cert.subject_alternative_name() {
+        for name in &ext.value.general_names {
+            match name {
+                GeneralName::DNSName(dns) => {
+                    if let Ok(dns) = Ia5String::try_from(*dns) {
+                        params.subject_alt_names.push(SanType::DnsName(dns));
+                    }
+                }
+                GeneralName::IPAddress(bytes) => {
+                    if let Some(ip) = ip_from_bytes(bytes) {
+                        params.subject_alt_names.push(SanType::IpAddress(ip));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // the client still has to accept the name it asked for
+    let requested = san_for(hostname)?;
+    if !params.subject_alt_names.contains(&requested) {
+        params.subject_alt_names.push(requested);
+    }
+
+    params.not_before = cert.validity().not_before.to_datetime();
+    params.not_after = cert.validity().not_after.to_datetime();
+
+    Ok(params)
+}
+
+async fn fetch_upstream_certificate(
+    host_map: &HostMap,
+    hostname: &str,
+    port: u16,
+) -> anyhow::Result<Vec<u8>> {
+    let stream = connect_upstream(host_map, hostname, port, &mut ConnectionInfo::default()).await?;
+
+    let client_config = rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_no_client_auth();
+
+    let server_name = ServerName::try_from(hostname.to_string())?;
+    let tls = TlsConnector::from(Arc::new(client_config))
+        .connect(server_name, stream)
+        .await?;
+
+    let (_, session) = tls.get_ref();
+    session
+        .peer_certificates()
+        .and_then(|c| c.first())
+        .map(|c| c.to_vec())
+        .ok_or_else(|| anyhow::anyhow!("servidor não apresentou certificado"))
+}
+
+#[derive(Debug)]
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _: &CertificateDer<'_>,
+        _: &[CertificateDer<'_>],
+        _: &ServerName<'_>,
+        _: &[u8],
+        _: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _: &[u8],
+        _: &CertificateDer<'_>,
+        _: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _: &[u8],
+        _: &CertificateDer<'_>,
+        _: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        rustls::crypto::CryptoProvider::get_default()
+            .map(|p| p.signature_verification_algorithms.supported_schemes())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeyAlgorithm {
+    EcdsaP256,
+    EcdsaP384,
+    Ed25519,
+}
+
+impl KeyAlgorithm {
+    fn generate(self) -> anyhow::Result<KeyPair> {
+        let alg = match self {
+            KeyAlgorithm::EcdsaP256 => &rcgen::PKCS_ECDSA_P256_SHA256,
+            KeyAlgorithm::EcdsaP384 => &rcgen::PKCS_ECDSA_P384_SHA384,
+            KeyAlgorithm::Ed25519 => &rcgen::PKCS_ED25519,
+        };
+        Ok(KeyPair::generate_for(alg)?)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CaOptions {
+    pub common_name: String,
+    pub organization: String,
+    pub key_algorithm: KeyAlgorithm,
+    pub validity_days: Option<u32>,
+    pub permitted_domains: Vec<String>,
+}
+
+impl Default for CaOptions {
+    fn default() -> Self {
+        Self {
+            common_name: "Mboi Tata Proxy Root CA".to_string(),
+            organization: "Mboi Tata".to_string(),
+            key_algorithm: KeyAlgorithm::EcdsaP256,
+            validity_days: None,
+            permitted_domains: vec![],
+        }
+    }
+}
+
+pub fn create_ca_certificate(options: &CaOptions) -> anyhow::Result<(String, String)> {
     let mut params = CertificateParams::default();
 
     params.is_ca = IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
@@ -78,20 +574,223 @@ pub fn create_ca_certificate() -> anyhow::Result<(String, String)> {
     ];
 
     let mut dn = DistinguishedName::new();
-    dn.push(DnType::CommonName, "Mboi Tata Proxy Root CA");
-    dn.push(DnType::OrganizationName, "Mboi Tata");
+    dn.push(DnType::CommonName, options.common_name.as_str());
+    dn.push(DnType::OrganizationName, options.organization.as_str());
     params.distinguished_name = dn;
 
-    let key = KeyPair::generate()?;
+    if !options.permitted_domains.is_empty() {
+        let permitted_subtrees: Vec<_> = options
+            .permitted_domains
+            .iter()
+            .map(|domain| match domain.parse::<IpAddr>() {
+                Ok(ip) => GeneralSubtree::IpAddress(CidrSubnet::from_addr_prefix(
+                    ip,
+                    if ip.is_ipv4() { 32 } else { 128 },
+                )),
+                Err(_) => GeneralSubtree::DnsName(domain.trim_start_matches("*.").to_string()),
+            })
+            .collect();
+
+        // without any IP subtree the IP name type would be unconstrained, so exclude every address
+        let excluded_subtrees = if permitted_subtrees
+            .iter()
+            .any(|s| matches!(s, GeneralSubtree::IpAddress(_)))
+        {
+            vec![]
+        } else {
+            vec![
+                GeneralSubtree::IpAddress(CidrSubnet::from_addr_prefix(Ipv4Addr::UNSPECIFIED.into(), 0)),
+                GeneralSubtree::IpAddress(CidrSubnet::from_addr_prefix(Ipv6Addr::UNSPECIFIED.into(), 0)),
+            ]
+        };
+
+        params.name_constraints = Some(NameConstraints {
+            permitted_subtrees,
+            excluded_subtrees,
+        });
+    }
+
+    if let Some(days) = options.validity_days {
+        let now = OffsetDateTime::now_utc();
+        params.not_before = now - TimeDuration::days(1);
+        params.not_after = now + TimeDuration::days(days.into());
+    }
+
+    let key = options.key_algorithm.generate()?;
     let cert = params.self_signed(&key)?;
 
     Ok((cert.pem(), key.serialize_pem()))
 }
 
+pub fn load_or_create_ca(certs_dir: &Path) -> anyhow::Result<(String, String)> {
+    let ca_cert_path = certs_dir.join(CA_CERT_FILE);
+    let ca_key_path = certs_dir.join(CA_KEY_FILE);
+
+    if ca_cert_path.exists() && ca_key_path.exists() {
+        return Ok((
+            fs::read_to_string(ca_cert_path)?,
+            fs::read_to_string(ca_key_path)?,
+        ));
+    }
+
+    fs::create_dir_all(certs_dir)?;
+    let (cert, key) = create_ca_certificate(&CaOptions::default())?;
+    fs::write(ca_cert_path, &cert)?;
+    write_private_key(&ca_key_path, &key)?;
+    clear_leafs(certs_dir)?;
+    Ok((cert, key))
+}
+
+// leafs signed by a previous CA are useless once it's replaced
+pub fn clear_leafs(certs_dir: &Path) -> anyhow::Result<()> {
+    let leaf_dir = certs_dir.join(LEAF_DIR);
+    if leaf_dir.exists() {
+        fs::remove_dir_all(&leaf_dir)?;
+    }
+    Ok(())
+}
+
 fn normalize_hostname(h: &str) -> String {
-    h.split(':')
-        .next()
-        .unwrap_or(h)
-        .trim_end_matches('.')
-        .to_ascii_lowercase()
+    let host = if let Some(bracketed) = h.strip_prefix('[') {
+        bracketed.split(']').next().unwrap_or(bracketed)
+    } else if h.parse::<IpAddr>().is_ok() {
+        h
+    } else {
+        h.split(':').next().unwrap_or(h)
+    };
+
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mboitata-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn forged(name: &str) -> Leaf {
+        Leaf {
+            name: name.to_string(),
+            mirrored: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn persisted_leafs_are_checked_against_the_current_ca_and_key() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let dir = storage("leafs");
+        let (cert, key) = create_ca_certificate(&CaOptions::default()).unwrap();
+        let manager = CertificateManager::new(&cert, &key).unwrap().with_storage(&dir).unwrap();
+        manager.get_server_config("example.com").await.unwrap();
+
+        let reloaded = CertificateManager::new(&cert, &key).unwrap().with_storage(&dir).unwrap();
+        assert!(reloaded.load_persisted(&forged("example.com")).is_some());
+
+        // same subject, different key
+        let (other_cert, other_key) = create_ca_certificate(&CaOptions::default()).unwrap();
+        let rotated = CertificateManager::new(&other_cert, &other_key)
+            .unwrap()
+            .with_storage(&dir)
+            .unwrap();
+        assert!(rotated.load_persisted(&forged("example.com")).is_none());
+
+        fs::remove_file(dir.join(LEAF_KEY_FILE)).unwrap();
+        let rekeyed = CertificateManager::new(&cert, &key).unwrap().with_storage(&dir).unwrap();
+        assert!(rekeyed.load_persisted(&forged("example.com")).is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn persisted_leafs_must_cover_the_requested_name() {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let dir = storage("names");
+        let (cert, key) = create_ca_certificate(&CaOptions::default()).unwrap();
+        let manager = CertificateManager::new(&cert, &key).unwrap().with_storage(&dir).unwrap();
+
+        let (first, second) = (forged("1::1"), forged("11::"));
+        assert_ne!(manager.leaf_path(&first), manager.leaf_path(&second));
+        assert_ne!(
+            manager.leaf_path(&first),
+            manager.leaf_path(&Leaf {
+                mirrored: true,
+                ..first.clone()
+            })
+        );
+
+        manager.get_server_config("[1::1]:443").await.unwrap();
+        assert!(manager.load_persisted(&first).is_some());
+
+        // a leaf for another name left at this path is not served
+        fs::copy(manager.leaf_path(&first).unwrap(), manager.leaf_path(&second).unwrap()).unwrap();
+        assert!(manager.load_persisted(&second).is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn scope(permitted: &[&str]) -> NameScope {
+        let (cert, _) = create_ca_certificate(&CaOptions {
+            permitted_domains: permitted.iter().map(|d| d.to_string()).collect(),
+            ..Default::default()
+        })
+        .unwrap();
+        let der = CertificateDer::from_pem_slice(cert.as_bytes()).unwrap();
+        let (_, ca) = X509Certificate::from_der(&der).unwrap();
+        NameScope::from_ca(&ca)
+    }
+
+    #[test]
+    fn unconstrained_ca_permits_everything() {
+        let scope = scope(&[]);
+        assert!(scope.permits("example.com"));
+        assert!(scope.permits("10.0.0.1"));
+        assert!(scope.permits("::1"));
+    }
+
+    #[test]
+    fn dns_constraints_match_the_domain_and_its_subdomains() {
+        let scope = scope(&["example.com", "*.test.org"]);
+        assert!(scope.permits("example.com"));
+        assert!(scope.permits("api.example.com"));
+        assert!(scope.permits("*.example.com"));
+        assert!(scope.permits("a.test.org"));
+        assert!(!scope.permits("badexample.com"));
+        assert!(!scope.permits("example.org"));
+    }
+
+    #[test]
+    fn dns_only_allowlist_excludes_every_ip() {
+        let scope = scope(&["example.com"]);
+        assert!(!scope.permits("10.0.0.1"));
+        assert!(!scope.permits("::1"));
+        assert!(!scope.permits_san(&SanType::IpAddress("127.0.0.1".parse().unwrap())));
+    }
+
+    #[test]
+    fn ip_constraints_only_permit_listed_addresses() {
+        let scope = scope(&["example.com", "10.0.0.1", "::1"]);
+        assert!(scope.permits("10.0.0.1"));
+        assert!(scope.permits("::1"));
+        assert!(!scope.permits("10.0.0.2"));
+        assert!(!scope.permits("::2"));
+        assert!(scope.permits("api.example.com"));
+        assert!(!scope.permits("example.org"));
+    }
+
+    #[test]
+    fn a_new_ca_clears_cached_leafs() {
+        let dir = storage("new-ca");
+        fs::create_dir_all(dir.join(LEAF_DIR)).unwrap();
+        fs::write(dir.join(LEAF_DIR).join("example.com.pem"), "stale").unwrap();
+
+        load_or_create_ca(&dir).unwrap();
+        assert!(!dir.join(LEAF_DIR).exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
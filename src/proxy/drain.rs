@@ -0,0 +1,123 @@
+use std::{error::Error as StdError, pin::Pin, time::Duration};
+
+use hyper::{
+    body::{Body, Incoming},
+    rt::{Read, Write},
+    server::conn::http1,
+    service::HttpService,
+};
+use tokio::sync::{mpsc, watch};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DrainState {
+    Running,
+    Draining,
+    Aborted,
+}
+
+pub trait GracefulConnection: Future<Output = Result<(), hyper::Error>> {
+    fn graceful_shutdown(self: Pin<&mut Self>);
+}
+
+impl<I, B, S> GracefulConnection for http1::Connection<I, S>
+where
+    S: HttpService<Incoming, ResBody = B>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    I: Read + Write + Unpin,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn graceful_shutdown(self: Pin<&mut Self>) {
+        http1::Connection::graceful_shutdown(self);
+    }
+}
+
+impl<I, B, S> GracefulConnection for http1::UpgradeableConnection<I, S>
+where
+    S: HttpService<Incoming, ResBody = B>,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+    I: Read + Write + Unpin + Send + 'static,
+    B: Body + 'static,
+    B::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    fn graceful_shutdown(self: Pin<&mut Self>) {
+        http1::UpgradeableConnection::graceful_shutdown(self);
+    }
+}
+
+pub struct Drain {
+    state: watch::Sender<DrainState>,
+    guard: mpsc::Sender<()>,
+    done: mpsc::Receiver<()>,
+}
+
+impl Drain {
+    pub fn new() -> Self {
+        let (state, _) = watch::channel(DrainState::Running);
+        let (guard, done) = mpsc::channel(1);
+        Self { state, guard, done }
+    }
+
+    pub fn watcher(&self) -> DrainWatcher {
+        DrainWatcher {
+            state: self.state.subscribe(),
+            _guard: self.guard.clone(),
+        }
+    }
+
+    pub async fn shutdown(self, timeout: Duration) {
+        let Self {
+            state,
+            guard,
+            mut done,
+        } = self;
+
+        let _ = state.send(DrainState::Draining);
+        drop(guard);
+
+        if tokio::time::timeout(timeout, done.recv()).await.is_err() {
+            println!(
+                "⏱️ Conexões ainda ativas após {}s. Abortando o restante...",
+                timeout.as_secs()
+            );
+            let _ = state.send(DrainState::Aborted);
+            done.recv().await;
+        }
+    }
+}
+
+impl Default for Drain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DrainWatcher {
+    state: watch::Receiver<DrainState>,
+    _guard: mpsc::Sender<()>,
+}
+
+impl DrainWatcher {
+    async fn draining(&mut self) {
+        let _ = self.state.wait_for(|s| *s != DrainState::Running).await;
+    }
+
+    pub async fn aborted(&mut self) {
+        let _ = self.state.wait_for(|s| *s == DrainState::Aborted).await;
+    }
+
+    pub async fn serve<C: GracefulConnection>(mut self, conn: C) -> Option<Result<(), hyper::Error>> {
+        let mut conn = std::pin::pin!(conn);
+
+        tokio::select! {
+            res = conn.as_mut() => return Some(res),
+            _ = self.draining() => conn.as_mut().graceful_shutdown(),
+        }
+
+        tokio::select! {
+            res = conn.as_mut() => Some(res),
+            _ = self.aborted() => None,
+        }
+    }
+}
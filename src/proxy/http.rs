@@ -1,20 +1,27 @@
-use std::pin::Pin;
+use std::{net::SocketAddr, pin::Pin};
 
 use http_body_util::combinators::BoxBody;
 use hyper::{
     Method, Request, Response,
     body::{Bytes, Incoming},
 };
-use tokio::{net::TcpStream, sync::mpsc::Sender};
+use tokio::sync::mpsc::Sender;
 
 use crate::{
-    proxy::{Intercept, empty, extract_host_port, handle_response, https::HttpsIntercept},
-    analyzer::intercepted::InterceptedResponse,
+    analyzer::intercepted::{ConnectionInfo, InterceptedResponse},
+    proxy::{
+        Exchange, Intercept, ProxyContext,
+        breakpoint::dropped_response,
+        connect_upstream, empty, extract_host_port, handle_response,
+        https::HttpsIntercept,
+    },
 };
 
 #[derive(Debug, Clone)]
 pub struct HttpIntercept {
     pub tx: Sender<InterceptedResponse>,
+    pub ctx: ProxyContext,
+    pub client_addr: SocketAddr,
     pub upgraded: HttpsIntercept,
 }
 
@@ -29,6 +36,8 @@ impl Intercept for HttpIntercept {
     fn call(&self, req: Self::Request) -> Self::Future {
         let tx = self.tx.clone();
         let upgraded = self.upgraded.clone();
+        let ctx = self.ctx.clone();
+        let client_addr = self.client_addr;
         Box::pin(async move {
             if Method::CONNECT == req.method() {
                 upgraded.upgraded(req).await
@@ -42,9 +51,20 @@ impl Intercept for HttpIntercept {
                         }
                     };
 
-                match TcpStream::connect((host.clone(), port)).await {
-                    Ok(server_tls_stream) => {
-                        handle_response(SCHEME.to_string(), host, req, tx.clone(), server_tls_stream).await
+                let conn_info = ConnectionInfo {
+                    client_addr: Some(client_addr),
+                    ..Default::default()
+                };
+                let mut exchange = Exchange::new(SCHEME, host.clone(), port, req.uri(), conn_info);
+
+                let req = match exchange.intercept_request(&ctx.breakpoints, req).await? {
+                    Some(req) => req,
+                    None => return Ok(dropped_response()),
+                };
+
+                match connect_upstream(&ctx.host_map, &host, port, &mut exchange.conn_info).await {
+                    Ok(stream) => {
+                        handle_response(exchange, req, tx.clone(), &ctx.breakpoints, stream).await
                     }
                     Err(err) => {
                         eprintln!("Erro ao estabelecer TLS com o servidor {}: {}", host, err);
@@ -1,4 +1,4 @@
-use std::{pin::Pin, sync::Arc};
+use std::{net::SocketAddr, pin::Pin, time::Instant};
 
 use http_body_util::combinators::BoxBody;
 use hyper::{
@@ -6,21 +6,27 @@ use hyper::{
 };
 use hyper_util::rt::TokioIo;
 use pki_types::ServerName;
-use rustls::RootCertStore;
-use tokio::{net::TcpStream, sync::mpsc::Sender};
-use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tokio::{io::copy_bidirectional, sync::mpsc::Sender};
+use tokio_rustls::TlsAcceptor;
 
 use crate::{
+    analyzer::intercepted::{ConnectionInfo, InterceptedResponse},
     proxy::{
-        certs::CertificateManager, create_response, empty, extract_host_port, full, handle_response, Intercept, InterceptService, ServerBuilder
+        Exchange, ProxyContext,
+        breakpoint::dropped_response,
+        connect_upstream, drain::DrainWatcher, create_response, empty, extract_host_port,
+        full, handle_response, split_host_port, tls_connector, Intercept, InterceptService,
+        ServerBuilder,
     },
-    analyzer::intercepted::InterceptedResponse,
+    recon::CertificateRecord,
 };
 
 #[derive(Debug, Clone)]
 pub struct HttpsIntercept {
     pub tx: Sender<InterceptedResponse>,
-    pub cert_manager: Arc<CertificateManager>,
+    pub ctx: ProxyContext,
+    pub client_addr: SocketAddr,
+    pub drain: DrainWatcher,
 }
 
 const SCHEME: &str = "https";
@@ -32,15 +38,21 @@ impl HttpsIntercept {
     ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error> {
         if let Some(addr) = host_addr(req.uri()) {
             let this = self.clone();
+            let mut watcher = self.drain.clone();
 
             tokio::task::spawn(async move {
-                match hyper::upgrade::on(req).await {
-                    Ok(upgraded) => {
-                        if let Err(e) = this.mitm_tunnel(upgraded, addr).await {
-                            eprintln!("HTTPS MITM error: {}", e);
+                tokio::select! {
+                    _ = watcher.aborted() => {}
+                    _ = async {
+                        match hyper::upgrade::on(req).await {
+                            Ok(upgraded) => {
+                                if let Err(e) = this.mitm_tunnel(upgraded, addr).await {
+                                    eprintln!("HTTPS MITM error: {}", e);
+                                }
+                            }
+                            Err(e) => eprintln!("upgrade error: {}", e),
                         }
-                    }
-                    Err(e) => eprintln!("upgrade error: {}", e),
+                    } => {}
                 }
             });
 
@@ -54,31 +66,50 @@ impl HttpsIntercept {
     }
 
     async fn mitm_tunnel(&self, upgraded: Upgraded, addr: String) -> anyhow::Result<()> {
-        let hostname = addr
-            .split_once(':')
-            .map(|(h, _)| h)
-            .unwrap_or(addr.as_str());
+        let (host, port) = split_host_port(&addr).unwrap_or((addr.as_str(), 443));
+        let host = host.to_ascii_lowercase();
+
+        if self.ctx.pinning.is_bypassed(&host) {
+            return self.passthrough(upgraded, &host, port).await;
+        }
 
-        let server_config = self.cert_manager.get_server_config(hostname).await?;
+        let server_config = self.ctx.cert_manager.get_server_config(&addr).await?;
         let acceptor = TlsAcceptor::from(server_config);
 
-        let client_tls_stream = acceptor.accept(TokioIo::new(upgraded)).await?;
+        let client_tls_stream = match acceptor.accept(TokioIo::new(upgraded)).await {
+            Ok(stream) => {
+                self.ctx.pinning.record_success(&host);
+                stream
+            }
+            Err(e) => {
+                self.ctx.pinning.record_failure(&host, &e);
+                return Err(e.into());
+            }
+        };
 
         let client_io = TokioIo::new(client_tls_stream);
 
         let https_server = InterceptService { base: self.clone() };
 
-        if let Err(err) = ServerBuilder::new()
+        let conn = ServerBuilder::new()
             .preserve_header_case(true)
             .title_case_headers(true)
-            .serve_connection(client_io, https_server)
-            .await
-        {
+            .serve_connection(client_io, https_server);
+
+        if let Some(Err(err)) = self.drain.clone().serve(conn).await {
             eprintln!("Failed to serve HTTPS connection: {:?}", err);
         }
 
         Ok(())
     }
+
+    async fn passthrough(&self, upgraded: Upgraded, host: &str, port: u16) -> anyhow::Result<()> {
+        let mut conn_info = ConnectionInfo::default();
+        let mut upstream = connect_upstream(&self.ctx.host_map, host, port, &mut conn_info).await?;
+
+        copy_bidirectional(&mut TokioIo::new(upgraded), &mut upstream).await?;
+        Ok(())
+    }
 }
 
 impl Intercept for HttpsIntercept {
@@ -89,6 +120,8 @@ impl Intercept for HttpsIntercept {
 
     fn call(&self, req: Self::Request) -> Self::Future {
         let tx = self.tx.clone();
+        let ctx = self.ctx.clone();
+        let client_addr = self.client_addr;
 
         Box::pin(async move {
             let (host, port) =
@@ -101,8 +134,19 @@ impl Intercept for HttpsIntercept {
                     }
                 };
 
+            let conn_info = ConnectionInfo {
+                client_addr: Some(client_addr),
+                ..Default::default()
+            };
+            let mut exchange = Exchange::new(SCHEME, host.clone(), port, req.uri(), conn_info);
+
+            let req = match exchange.intercept_request(&ctx.breakpoints, req).await? {
+                Some(req) => req,
+                None => return Ok(dropped_response()),
+            };
+
             let server_name = ServerName::try_from(host.clone()).unwrap();
-            let stream = match TcpStream::connect((host.clone(), port)).await {
+            let stream = match connect_upstream(&ctx.host_map, &host, port, &mut exchange.conn_info).await {
                 Ok(s) => s,
                 Err(e) => {
                     let msg = format!("Erro ao connectar ao servidor host/port: {:?}", e);
@@ -111,17 +155,31 @@ impl Intercept for HttpsIntercept {
                 }
             };
 
-            let root_store = RootCertStore {
-                roots: webpki_roots::TLS_SERVER_ROOTS.into(),
-            };
-            let client_config = rustls::ClientConfig::builder()
-                .with_root_certificates(root_store)
-                .with_no_client_auth();
-
-            let connector = TlsConnector::from(Arc::new(client_config));
+            let connector = tls_connector();
+            let tls_started = Instant::now();
             match connector.connect(server_name, stream).await {
                 Ok(server_tls_stream) => {
-                    handle_response(SCHEME.to_string(), host, req, tx.clone(), server_tls_stream).await
+                    let conn_info = &mut exchange.conn_info;
+                    conn_info.timings.tls = Some(tls_started.elapsed());
+
+                    let (_, session) = server_tls_stream.get_ref();
+                    conn_info.tls_version = session.protocol_version().map(|v| format!("{:?}", v));
+                    conn_info.tls_cipher = session
+                        .negotiated_cipher_suite()
+                        .map(|c| format!("{:?}", c.suite()));
+
+                    let leaf = session
+                        .peer_certificates()
+                        .and_then(|c| c.first())
+                        .filter(|_| ctx.recon.wants_certificate(&host));
+                    if let Some(leaf) = leaf {
+                        match CertificateRecord::from_der(&host, leaf) {
+                            Ok(record) => ctx.recon.record_certificate(record),
+                            Err(e) => eprintln!("❌ {e}"),
+                        }
+                    }
+
+                    handle_response(exchange, req, tx.clone(), &ctx.breakpoints, server_tls_stream).await
                 }
                 Err(err) => {
                     eprintln!("Erro ao estabelecer TLS com o servidor {}: {}", host, err);
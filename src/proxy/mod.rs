@@ -1,28 +1,50 @@
+pub mod breakpoint;
 pub mod certs;
 
+mod drain;
 mod http;
 mod https;
+pub mod pinning;
 
-use std::sync::Arc;
+use std::{
+    io,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use anyhow::bail;
 use http_body_util::{BodyExt, Empty, Full, combinators::BoxBody};
 use hyper::{
-    HeaderMap, Request, Response, StatusCode, Version,
+    HeaderMap, Request, Response, StatusCode, Uri, Version,
     body::{Bytes, Incoming},
     header::{self, HeaderValue},
     service::Service,
 };
 use hyper_util::rt::TokioIo;
+use pki_types::ServerName;
+use rustls::RootCertStore;
 use tokio::{
     io::{AsyncRead, AsyncWrite},
-    net::TcpListener,
+    net::{TcpListener, TcpStream},
     sync::{broadcast, mpsc::Sender},
 };
+use tokio_rustls::TlsConnector;
 
+use crate::proxy::breakpoint::{Breakpoints, ResponseParts, dropped_response};
+use crate::proxy::drain::Drain;
 use crate::proxy::http::HttpIntercept;
 use crate::proxy::https::HttpsIntercept;
-use crate::{proxy::certs::CertificateManager, analyzer::intercepted::InterceptedResponse};
+use crate::proxy::pinning::PinningTracker;
+use crate::{
+    analyzer::{
+        event::Provenance,
+        intercepted::{ConnectionInfo, ExchangeRecord, InterceptedResponse, header_pairs, url_host},
+    },
+    host_map::HostMap,
+    proxy::certs::CertificateManager,
+    recon::Recon,
+};
 
 type ClientBuilder = hyper::client::conn::http1::Builder;
 type ServerBuilder = hyper::server::conn::http1::Builder;
@@ -36,6 +58,15 @@ trait Intercept: Send + Sync + 'static {
     fn call(&self, req: Self::Request) -> Self::Future;
 }
 
+#[derive(Debug, Clone)]
+pub struct ProxyContext {
+    pub cert_manager: Arc<CertificateManager>,
+    pub host_map: HostMap,
+    pub recon: Recon,
+    pub breakpoints: Breakpoints,
+    pub pinning: PinningTracker,
+}
+
 #[derive(Debug, Clone)]
 struct InterceptService<I: Intercept> {
     base: I,
@@ -54,31 +85,37 @@ impl<I: Intercept> Service<I::Request> for InterceptService<I> {
 pub async fn start_proxy(
     tx: Sender<InterceptedResponse>,
     mut kill_signal: broadcast::Receiver<()>,
-    cert_manager: Arc<CertificateManager>,
+    ctx: ProxyContext,
     proxy_server: &str,
+    drain_timeout: Duration,
 ) -> anyhow::Result<()> {
     println!("Starting listener on {proxy_server}");
 
     let listener = TcpListener::bind(proxy_server).await?;
+    let drain = Drain::new();
 
     loop {
         tokio::select! {
             _ = kill_signal.recv() => {
                 println!("🛑 Proxy recebeu kill. Encerrando listener...");
-                return Ok(());
+                break;
             }
             res = listener.accept() => {
                 match res {
-                    Ok((stream, _)) => {
+                    Ok((stream, client_addr)) => {
                         let io = TokioIo::new(stream);
 
                         let https_intercept = HttpsIntercept {
                             tx: tx.clone(),
-                            cert_manager: cert_manager.clone(),
+                            ctx: ctx.clone(),
+                            client_addr,
+                            drain: drain.watcher(),
                         };
 
                         let http_intercept = HttpIntercept {
                             tx: tx.clone(),
+                            ctx: ctx.clone(),
+                            client_addr,
                             upgraded: https_intercept.clone(),
                         };
 
@@ -86,14 +123,15 @@ pub async fn start_proxy(
                             base: http_intercept.clone(),
                         };
 
+                        let watcher = drain.watcher();
                         tokio::task::spawn(async move {
-                            if let Err(err) = ServerBuilder::new()
+                            let conn = ServerBuilder::new()
                                 .preserve_header_case(true)
                                 .title_case_headers(true)
                                 .serve_connection(io, http_service)
-                                .with_upgrades()
-                                .await
-                            {
+                                .with_upgrades();
+
+                            if let Some(Err(err)) = watcher.serve(conn).await {
                                 println!("Failed to serve connection: {:?}", err);
                             }
                         });
@@ -106,6 +144,13 @@ pub async fn start_proxy(
             }
         }
     }
+
+    drop(listener);
+    println!("⏳ Aguardando conexões em andamento finalizarem...");
+    drain.shutdown(drain_timeout).await;
+    println!("✅ Proxy drenado. Fechando fila de respostas.");
+
+    Ok(())
 }
 
 fn rebuild_response(
@@ -134,7 +179,7 @@ fn create_response(
     resp
 }
 
-fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, hyper::Error> {
+pub fn full<T: Into<Bytes>>(chunk: T) -> BoxBody<Bytes, hyper::Error> {
     Full::new(chunk.into())
         .map_err(|never| match never {})
         .boxed()
@@ -146,19 +191,70 @@ fn empty() -> BoxBody<Bytes, hyper::Error> {
         .boxed()
 }
 
-async fn handle_response<T>(
+struct Exchange {
     scheme: String,
     host: String,
-    req: Request<Incoming>,
+    url: String,
+    conn_info: ConnectionInfo,
+    started: Instant,
+}
+
+impl Exchange {
+    fn new(scheme: &str, host: String, port: u16, uri: &Uri, conn_info: ConnectionInfo) -> Self {
+        let authority = match (scheme, port) {
+            ("http", 80) | ("https", 443) => url_host(&host),
+            _ => format!("{}:{port}", url_host(&host)),
+        };
+        let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+        Self {
+            scheme: scheme.to_string(),
+            url: format!("{scheme}://{authority}{path}"),
+            host,
+            conn_info,
+            started: Instant::now(),
+        }
+    }
+
+    // the clock restarts once the breakpoint lets the request through, so time spent paused isn't timed
+    async fn intercept_request(
+        &mut self,
+        breakpoints: &Breakpoints,
+        req: Request<Incoming>,
+    ) -> Result<Option<Request<BoxBody<Bytes, hyper::Error>>>, hyper::Error> {
+        let req = breakpoints.intercept_request(&self.url, req).await?;
+        self.started = Instant::now();
+        Ok(req)
+    }
+}
+
+async fn handle_response<T>(
+    exchange: Exchange,
+    req: Request<BoxBody<Bytes, hyper::Error>>,
     tx: Sender<InterceptedResponse>,
+    breakpoints: &Breakpoints,
     io: T,
 ) -> Result<Response<BoxBody<Bytes, hyper::Error>>, hyper::Error>
 where
     T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
+    let Exchange {
+        scheme,
+        host,
+        url,
+        mut conn_info,
+        started,
+    } = exchange;
+
     let server_io = TokioIo::new(io);
     let uri = req.uri().clone();
 
+    let (parts, body) = req.into_parts();
+    let request_body = body.collect().await?.to_bytes();
+    let method = parts.method.to_string();
+    let request_headers = header_pairs(&parts.headers);
+    let req = Request::from_parts(parts, full(request_body.clone()));
+
     let (mut sender, conn) = ClientBuilder::new()
         .preserve_header_case(true)
         .title_case_headers(true)
@@ -171,27 +267,136 @@ where
         }
     });
 
+    let sent = Instant::now();
     let mut resp = sender.send_request(req).await?;
+    conn_info.timings.ttfb = Some(sent.elapsed());
+
     let status = resp.status();
     let version = resp.version();
 
     let body_bytes = resp.body_mut().collect().await?.to_bytes();
     let body_for_client = body_bytes.clone();
+    conn_info.timings.total = Some(started.elapsed());
 
-    let intercepted = InterceptedResponse::new(
+    let mut intercepted = InterceptedResponse::new(
         uri.path().to_string(),
         scheme,
         host,
         resp.headers(),
-        body_bytes.to_vec(),
+        body_bytes.clone(),
+        conn_info,
+    );
+    intercepted.provenance = Provenance::captured(
+        request_headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("referer"))
+            .map(|(_, value)| value.clone()),
     );
+    intercepted.exchange = Some(Arc::new(ExchangeRecord {
+        method,
+        url: url.clone(),
+        request_headers,
+        request_body: request_body.to_vec(),
+        status: status.as_u16(),
+        response_headers: header_pairs(resp.headers()),
+    }));
 
     if let Err(err) = tx.send(intercepted).await {
         eprintln!("Erro ao enviar para fila: {err}");
     }
 
-    let new_resp = rebuild_response(status, version, resp.headers(), body_for_client);
-    Ok(new_resp)
+    let original = ResponseParts {
+        status,
+        headers: resp.headers().clone(),
+        body: body_for_client,
+    };
+
+    match breakpoints.intercept_response(&url, version, original).await {
+        Some(parts) => Ok(rebuild_response(parts.status, version, &parts.headers, parts.body)),
+        None => Ok(dropped_response()),
+    }
+}
+
+fn tls_connector() -> TlsConnector {
+    let root_store = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.into(),
+    };
+    let client_config = rustls::ClientConfig::builder()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+
+    TlsConnector::from(Arc::new(client_config))
+}
+
+pub async fn send_upstream(
+    host_map: &HostMap,
+    scheme: &str,
+    host: &str,
+    port: u16,
+    req: Request<BoxBody<Bytes, hyper::Error>>,
+) -> anyhow::Result<Response<Incoming>> {
+    let host = host.trim_matches(['[', ']']);
+    let stream = connect_upstream(host_map, host, port, &mut ConnectionInfo::default()).await?;
+
+    if scheme == "https" {
+        let server_name = ServerName::try_from(host.to_string())?;
+        let tls_stream = tls_connector().connect(server_name, stream).await?;
+        send_over(tls_stream, req).await
+    } else {
+        send_over(stream, req).await
+    }
+}
+
+async fn send_over<T>(
+    io: T,
+    req: Request<BoxBody<Bytes, hyper::Error>>,
+) -> anyhow::Result<Response<Incoming>>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, conn) = ClientBuilder::new()
+        .preserve_header_case(true)
+        .title_case_headers(true)
+        .handshake(TokioIo::new(io))
+        .await?;
+
+    tokio::task::spawn(async move {
+        if let Err(err) = conn.await {
+            eprintln!("Connection failed: {:?}", err);
+        }
+    });
+
+    Ok(sender.send_request(req).await?)
+}
+
+pub async fn connect_upstream(
+    host_map: &HostMap,
+    host: &str,
+    port: u16,
+    conn_info: &mut ConnectionInfo,
+) -> io::Result<TcpStream> {
+    let (target, port) = host_map.resolve(host, port);
+
+    let started = Instant::now();
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((target, port)).await?.collect();
+    conn_info.timings.dns = Some(started.elapsed());
+
+    let started = Instant::now();
+    let mut last_err = None;
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => {
+                conn_info.timings.connect = Some(started.elapsed());
+                conn_info.upstream_addr = Some(addr);
+                return Ok(stream);
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("nenhum endereço para {host}"))
+    }))
 }
 
 pub fn extract_host_port(
@@ -204,7 +409,7 @@ pub fn extract_host_port(
         let port = uri
             .port_u16()
             .unwrap_or_else(|| default_port(uri.scheme_str().or(scheme)));
-        return Ok((host.to_string(), port));
+        return Ok((host.trim_matches(['[', ']']).to_string(), port));
     }
 
     if let Some(host_hdr) = host_like_header(req.headers()) {
@@ -239,7 +444,7 @@ fn host_like_header(headers: &HeaderMap) -> Option<&str> {
     None
 }
 
-fn split_host_port(s: &str) -> Option<(&str, u16)> {
+pub fn split_host_port(s: &str) -> Option<(&str, u16)> {
     if let Some(stripped) = s.strip_prefix('[') {
         if let Some(end) = stripped.find(']') {
             let host = &stripped[..end];
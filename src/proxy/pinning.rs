@@ -0,0 +1,169 @@
+use std::{
+    collections::HashMap,
+    fmt, io,
+    sync::{Arc, Mutex},
+};
+
+use rustls::AlertDescription;
+
+use crate::{
+    analyzer::{
+        event::{CAPTURED, Dispatcher},
+        finding::Finding,
+    },
+    recon::Recon,
+};
+
+#[derive(Clone)]
+pub struct PinningTracker {
+    threshold: u32,
+    failures: Arc<Mutex<HashMap<String, u32>>>,
+    recon: Recon,
+    findings: Option<Dispatcher>,
+}
+
+impl PinningTracker {
+    pub fn new(threshold: u32, recon: Recon) -> Self {
+        Self {
+            threshold,
+            failures: Arc::new(Mutex::new(HashMap::new())),
+            recon,
+            findings: None,
+        }
+    }
+
+    pub fn reporting(mut self, dispatcher: Dispatcher) -> Self {
+        self.findings = Some(dispatcher);
+        self
+    }
+
+    pub fn is_bypassed(&self, host: &str) -> bool {
+        self.threshold > 0
+            && self
+                .failures
+                .lock()
+                .unwrap()
+                .get(host)
+                .is_some_and(|n| *n >= self.threshold)
+    }
+
+    pub fn record_success(&self, host: &str) {
+        self.failures.lock().unwrap().remove(host);
+    }
+
+    pub fn record_failure(&self, host: &str, err: &io::Error) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let Some(reason) = rejection_reason(err) else {
+            return;
+        };
+
+        let count = {
+            let mut failures = self.failures.lock().unwrap();
+            let count = failures.entry(host.to_string()).or_insert(0);
+            *count += 1;
+            *count
+        };
+
+        println!(
+            "🔒 [Pinning] {host} rejeitou o certificado forjado ({reason}), falha {count}/{}",
+            self.threshold
+        );
+
+        if count == self.threshold {
+            println!(
+                "🔀 [Pinning] {host} provavelmente usa certificate pinning. Seguindo em passthrough."
+            );
+            self.recon.record_pinned(host, count, &reason);
+            if let Some(dispatcher) = &self.findings {
+                dispatcher.report(
+                    Finding::for_host(CAPTURED, "pinning", host, host)
+                        .detail(format!("{count} handshake(s) rejeitado(s): {reason}")),
+                );
+            }
+        }
+    }
+}
+
+impl fmt::Debug for PinningTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinningTracker")
+            .field("threshold", &self.threshold)
+            .field("failures", &self.failures)
+            .field("recon", &self.recon)
+            .finish()
+    }
+}
+
+// only failures that look like the client refusing our certificate count towards pinning
+fn rejection_reason(err: &io::Error) -> Option<String> {
+    if let Some(rustls::Error::AlertReceived(alert)) = err
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<rustls::Error>())
+    {
+        return match alert {
+            AlertDescription::BadCertificate
+            | AlertDescription::UnknownCA
+            | AlertDescription::CertificateUnknown
+            | AlertDescription::UnsupportedCertificate
+            | AlertDescription::AccessDenied => Some(format!("alerta {:?}", alert)),
+            _ => None,
+        };
+    }
+
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted => Some("conexão encerrada no handshake".to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        allow_list::AllowList,
+        analyzer::sink::{FindingSink, FindingSinks},
+    };
+
+    struct Collect(Arc<Mutex<Vec<Finding>>>);
+
+    impl FindingSink for Collect {
+        fn report(&self, finding: &Finding) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(finding.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reaching_the_threshold_reports_a_pinning_finding() {
+        let output = std::env::temp_dir().join(format!("mboitata-pinning-{}", std::process::id()));
+        let findings = Arc::new(Mutex::new(vec![]));
+        let (tx, _) = tokio::sync::broadcast::channel(1);
+        let dispatcher = Dispatcher::new(
+            Default::default(),
+            vec![],
+            FindingSinks::new(vec![Box::new(Collect(findings.clone()))]),
+            crate::analyzer::event::QueuePolicy::Block,
+            tx,
+        );
+        let tracker = PinningTracker::new(2, Recon::new(&output, AllowList::new(vec![]))).reporting(dispatcher);
+
+        let reset = io::Error::from(io::ErrorKind::ConnectionReset);
+        tracker.record_failure("app.example.com", &reset);
+        assert!(findings.lock().unwrap().is_empty());
+
+        tracker.record_failure("app.example.com", &reset);
+        let findings = findings.lock().unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!((findings[0].kind.as_str(), findings[0].host.as_str()), ("pinning", "app.example.com"));
+        assert!(tracker.is_bypassed("app.example.com"));
+
+        let _ = fs::remove_dir_all(output);
+    }
+}
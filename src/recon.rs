@@ -0,0 +1,191 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs,
+    io::Write,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use sanitize_filename::sanitize;
+use x509_parser::{
+    extensions::GeneralName,
+    prelude::{FromDer, X509Certificate},
+    public_key::PublicKey,
+};
+
+use crate::allow_list::AllowList;
+
+#[derive(Debug, Clone)]
+pub struct CertificateRecord {
+    pub host: String,
+    pub subject: String,
+    pub issuer: String,
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub key_type: String,
+    pub san: Vec<String>,
+}
+
+impl CertificateRecord {
+    pub fn from_der(host: &str, der: &[u8]) -> anyhow::Result<Self> {
+        let (_, cert) = X509Certificate::from_der(der)
+            .map_err(|e| anyhow::anyhow!("certificado inválido de {host}: {e}"))?;
+
+        let mut san = vec![];
+        if let Ok(Some(ext)) = cert.subject_alternative_name() {
+            for name in &ext.value.general_names {
+                match name {
+                    GeneralName::DNSName(dns) => san.push(dns.to_string()),
+                    GeneralName::IPAddress(bytes) => {
+                        if let Some(ip) = ip_from_bytes(bytes) {
+                            san.push(ip.to_string());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let key_type = match cert.public_key().parsed() {
+            Ok(PublicKey::RSA(rsa)) => format!("RSA {}", rsa.key_size()),
+            Ok(PublicKey::EC(ec)) => format!("EC P-{}", ec.key_size()),
+            _ => cert.public_key().algorithm.algorithm.to_id_string(),
+        };
+
+        Ok(Self {
+            host: host.to_string(),
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            serial: cert.raw_serial_as_string(),
+            not_before: cert.validity().not_before.to_string(),
+            not_after: cert.validity().not_after.to_string(),
+            key_type,
+            san,
+        })
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "Host: {}\nSubject: {}\nIssuer: {}\nSerial: {}\nNot Before: {}\nNot After: {}\nKey: {}\nSAN:\n{}\n",
+            self.host,
+            self.subject,
+            self.issuer,
+            self.serial,
+            self.not_before,
+            self.not_after,
+            self.key_type,
+            self.san
+                .iter()
+                .map(|s| format!("  {s}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Recon {
+    dir: PathBuf,
+    allow_list: AllowList,
+    seen_certs: Arc<Mutex<HashSet<String>>>,
+    hosts: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl Recon {
+    pub fn new(output: &Path, allow_list: AllowList) -> Self {
+        Self {
+            dir: output.join("recon"),
+            allow_list,
+            seen_certs: Arc::new(Mutex::new(HashSet::new())),
+            hosts: Arc::new(Mutex::new(BTreeSet::new())),
+        }
+    }
+
+    pub fn wants_certificate(&self, host: &str) -> bool {
+        self.allow_list.in_scope(host) && !self.seen_certs.lock().unwrap().contains(host)
+    }
+
+    pub fn record_certificate(&self, record: CertificateRecord) {
+        if !self.seen_certs.lock().unwrap().insert(record.host.clone()) {
+            return;
+        }
+
+        println!(
+            "[Recon] Certificado de {}: {} SAN(s), emitido por {}",
+            record.host,
+            record.san.len(),
+            record.issuer
+        );
+
+        let certs_dir = self.dir.join("certs");
+        if let Err(e) = fs::create_dir_all(&certs_dir) {
+            eprintln!("Erro ao criar diretório {:?}: {}", certs_dir, e);
+            return;
+        }
+
+        let file = certs_dir.join(format!("{}.txt", sanitize(&record.host)));
+        if let Err(e) = fs::write(&file, record.render()) {
+            eprintln!("Erro ao salvar o arquivo {:?}: {}", file, e);
+        }
+
+        let mut discovered = vec![record.host.as_str()];
+        discovered.extend(record.san.iter().map(|s| s.trim_start_matches("*.")));
+        self.record_hosts(&discovered);
+    }
+
+    pub fn record_pinned(&self, host: &str, failures: u32, reason: &str) {
+        if let Err(e) = fs::create_dir_all(&self.dir) {
+            eprintln!("Erro ao criar diretório {:?}: {}", self.dir, e);
+            return;
+        }
+
+        let file = self.dir.join("pinned.txt");
+        let written = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&file)
+            .and_then(|mut f| writeln!(f, "{host}\t{failures} falha(s)\t{reason}"));
+
+        if let Err(e) = written {
+            eprintln!("Erro ao salvar o arquivo {:?}: {}", file, e);
+        }
+    }
+
+    pub fn record_hosts(&self, hosts: &[&str]) {
+        let snapshot = {
+            let mut known = self.hosts.lock().unwrap();
+            let before = known.len();
+            for host in hosts {
+                let host = host.trim_end_matches('.').to_ascii_lowercase();
+                if !host.is_empty() {
+                    known.insert(host);
+                }
+            }
+
+            if known.len() == before {
+                return;
+            }
+            known.iter().cloned().collect::<Vec<_>>().join("\n")
+        };
+
+        if let Err(e) = fs::create_dir_all(&self.dir) {
+            eprintln!("Erro ao criar diretório {:?}: {}", self.dir, e);
+            return;
+        }
+
+        let file = self.dir.join("hosts.txt");
+        if let Err(e) = fs::write(&file, snapshot) {
+            eprintln!("Erro ao salvar o arquivo {:?}: {}", file, e);
+        }
+    }
+}
+
+pub fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => <[u8; 4]>::try_from(bytes).ok().map(IpAddr::from),
+        16 => <[u8; 16]>::try_from(bytes).ok().map(IpAddr::from),
+        _ => None,
+    }
+}
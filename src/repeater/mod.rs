@@ -0,0 +1,179 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, bail};
+use http_body_util::BodyExt;
+use hyper::{
+    HeaderMap, Method, Request,
+    header::{self, HeaderName, HeaderValue},
+};
+use similar::TextDiff;
+use url::Url;
+
+use crate::{
+    analyzer::{
+        intercepted::{ConnectionInfo, InterceptedResponse, header_pairs},
+        stages::archive_stage::{ArchiveEntry, load_archive},
+    },
+    config::RepeatArgs,
+    host_map::HostMap,
+    proxy::{full, send_upstream},
+};
+
+pub async fn run(args: &RepeatArgs, archive: &Path, host_map: HostMap) -> anyhow::Result<()> {
+    let entries = load_archive(archive)
+        .map_err(|e| anyhow!("não foi possível ler o arquivo {:?}: {}", archive, e))?;
+
+    if args.list {
+        for entry in &entries {
+            println!(
+                "{}\t{}\t{}\t{}",
+                entry.id, entry.exchange.status, entry.exchange.method, entry.exchange.url
+            );
+        }
+        return Ok(());
+    }
+
+    let id = args.id.ok_or_else(|| anyhow!("informe --id ou --list"))?;
+    let original = entries
+        .into_iter()
+        .find(|e| e.id == id)
+        .ok_or_else(|| anyhow!("exchange {id} não encontrado em {:?}", archive))?;
+
+    let mut url = Url::parse(&original.exchange.url)?;
+    apply_params(&mut url, &args.params)?;
+
+    let method = match &args.method {
+        Some(m) => Method::from_bytes(m.to_ascii_uppercase().as_bytes())?,
+        None => Method::from_bytes(original.exchange.method.as_bytes())?,
+    };
+
+    let body = match (&args.body, &args.body_file) {
+        (Some(body), _) => body.clone().into_bytes(),
+        (_, Some(path)) => fs::read(path)?,
+        _ => original.exchange.request_body.clone(),
+    };
+
+    let headers = build_headers(&original, args, body.len())?;
+
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("URL sem host: {url}"))?
+        .to_string();
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| anyhow!("URL sem porta: {url}"))?;
+
+    let path = match url.query() {
+        Some(q) => format!("{}?{}", url.path(), q),
+        None => url.path().to_string(),
+    };
+
+    let mut req = Request::new(full(body));
+    *req.method_mut() = method.clone();
+    *req.uri_mut() = path.parse()?;
+    *req.headers_mut() = headers;
+
+    println!("🔁 Reenviando exchange {id}: {method} {url}");
+    let mut resp = send_upstream(&host_map, url.scheme(), &host, port, req).await?;
+    let status = resp.status();
+    let resp_headers = resp.headers().clone();
+    let resp_body = resp.body_mut().collect().await?.to_bytes();
+
+    println!("⬅️ {} ({} bytes)", status, resp_body.len());
+
+    let before = render_response(
+        original.exchange.status,
+        &original.exchange.response_headers,
+        &decoded_body(&url, &original.response_body, &header_map(&original.exchange.response_headers)?),
+    );
+    let after = render_response(
+        status.as_u16(),
+        &header_pairs(&resp_headers),
+        &decoded_body(&url, &resp_body, &resp_headers),
+    );
+
+    let diff = TextDiff::from_lines(&before, &after);
+    print!(
+        "{}",
+        diff.unified_diff()
+            .context_radius(3)
+            .header(&format!("original #{id}"), "repetido")
+    );
+
+    Ok(())
+}
+
+fn apply_params(url: &mut Url, params: &[String]) -> anyhow::Result<()> {
+    if params.is_empty() {
+        return Ok(());
+    }
+
+    let mut pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    for param in params {
+        let (key, value) = param
+            .split_once('=')
+            .ok_or_else(|| anyhow!("parâmetro inválido '{param}', esperado chave=valor"))?;
+
+        pairs.retain(|(k, _)| k != key);
+        pairs.push((key.to_string(), value.to_string()));
+    }
+
+    url.query_pairs_mut().clear().extend_pairs(pairs);
+    Ok(())
+}
+
+fn header_map(pairs: &[(String, String)]) -> anyhow::Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (k, v) in pairs {
+        headers.append(HeaderName::from_bytes(k.as_bytes())?, HeaderValue::from_str(v)?);
+    }
+    Ok(headers)
+}
+
+fn build_headers(original: &ArchiveEntry, args: &RepeatArgs, body_len: usize) -> anyhow::Result<HeaderMap> {
+    let mut headers = header_map(&original.exchange.request_headers)?;
+
+    for name in &args.remove_header {
+        headers.remove(HeaderName::from_bytes(name.as_bytes())?);
+    }
+
+    for header in &args.headers {
+        let Some((name, value)) = header.split_once(':') else {
+            bail!("header inválido '{header}', esperado 'Nome: valor'");
+        };
+        headers.insert(
+            HeaderName::from_bytes(name.trim().as_bytes())?,
+            HeaderValue::from_str(value.trim())?,
+        );
+    }
+
+    headers.remove(header::TRANSFER_ENCODING);
+    headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body_len));
+    Ok(headers)
+}
+
+fn decoded_body(url: &Url, body: &[u8], headers: &HeaderMap) -> String {
+    let resp = InterceptedResponse::new(
+        url.path().to_string(),
+        url.scheme().to_string(),
+        url.host_str().unwrap_or_default().to_string(),
+        headers,
+        body.to_vec(),
+        ConnectionInfo::default(),
+    );
+
+    resp.get_body()
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("<{} bytes binários>", body.len()))
+}
+
+fn render_response(status: u16, headers: &[(String, String)], body: &str) -> String {
+    let mut headers = headers
+        .iter()
+        .filter(|(k, _)| !k.eq_ignore_ascii_case("date"))
+        .map(|(k, v)| format!("{}: {}", k.to_ascii_lowercase(), v))
+        .collect::<Vec<_>>();
+    headers.sort();
+
+    format!("HTTP {}\n{}\n\n{}\n", status, headers.join("\n"), body)
+}
@@ -0,0 +1,484 @@
+pub mod report;
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex, mpsc},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::analyzer::{
+    content_store::content_hash, finding::Finding, intercepted::InterceptedResponse, sink::FindingSink,
+};
+
+pub const SESSION_FILE: &str = "session.db";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS hosts (
+    name TEXT PRIMARY KEY,
+    scheme TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    exchanges INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS endpoints (
+    id INTEGER PRIMARY KEY,
+    host TEXT NOT NULL REFERENCES hosts(name),
+    method TEXT NOT NULL,
+    path TEXT NOT NULL,
+    last_status INTEGER,
+    hits INTEGER NOT NULL DEFAULT 0,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    UNIQUE (host, method, path)
+);
+
+CREATE TABLE IF NOT EXISTS bodies (
+    hash TEXT PRIMARY KEY,
+    size INTEGER NOT NULL,
+    content_type TEXT NOT NULL,
+    body BLOB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS exchanges (
+    id INTEGER PRIMARY KEY,
+    at INTEGER NOT NULL,
+    method TEXT NOT NULL,
+    url TEXT NOT NULL,
+    host TEXT NOT NULL REFERENCES hosts(name),
+    endpoint_id INTEGER REFERENCES endpoints(id),
+    status INTEGER NOT NULL,
+    content_type TEXT NOT NULL,
+    content_encoding TEXT NOT NULL,
+    request_headers TEXT NOT NULL,
+    request_body BLOB NOT NULL,
+    response_headers TEXT NOT NULL,
+    body_hash TEXT REFERENCES bodies(hash),
+    conn TEXT NOT NULL,
+    event_id INTEGER NOT NULL,
+    page TEXT
+);
+CREATE INDEX IF NOT EXISTS exchanges_host ON exchanges (host);
+CREATE INDEX IF NOT EXISTS exchanges_body ON exchanges (body_hash);
+
+CREATE TABLE IF NOT EXISTS findings (
+    id INTEGER PRIMARY KEY,
+    at INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    value TEXT NOT NULL,
+    source TEXT NOT NULL,
+    host TEXT NOT NULL,
+    stage TEXT NOT NULL,
+    line INTEGER,
+    col INTEGER,
+    confidence TEXT NOT NULL,
+    detail TEXT,
+    event_id INTEGER NOT NULL,
+    parent_id INTEGER,
+    trail TEXT NOT NULL
+);
+CREATE UNIQUE INDEX IF NOT EXISTS findings_unique
+    ON findings (kind, value, source, IFNULL(line, 0), IFNULL(col, 0));
+CREATE INDEX IF NOT EXISTS findings_host ON findings (host, kind);
+
+CREATE TABLE IF NOT EXISTS body_urls (
+    url TEXT NOT NULL,
+    hash TEXT NOT NULL REFERENCES bodies(hash),
+    at INTEGER NOT NULL,
+    PRIMARY KEY (url, hash)
+);
+
+CREATE TABLE IF NOT EXISTS analyzed_bodies (
+    hash TEXT PRIMARY KEY REFERENCES bodies(hash),
+    at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS crawl_urls (
+    url TEXT PRIMARY KEY,
+    added_at INTEGER NOT NULL,
+    visited_at INTEGER
+);
+";
+
+const SCHEMA_VERSION: i64 = 1;
+
+// columns added to tables of older databases; a new database gets them straight from SCHEMA
+const MIGRATIONS: &[(i64, &str)] = &[(
+    1,
+    "ALTER TABLE exchanges ADD COLUMN event_id INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE exchanges ADD COLUMN page TEXT;
+     ALTER TABLE findings ADD COLUMN event_id INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE findings ADD COLUMN parent_id INTEGER;
+     ALTER TABLE findings ADD COLUMN trail TEXT NOT NULL DEFAULT '[]';",
+)];
+
+fn migrate(conn: &mut Connection) -> anyhow::Result<()> {
+    let version: i64 = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let existing: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'exchanges')",
+        [],
+        |row| row.get(0),
+    )?;
+
+    let tx = conn.transaction()?;
+    if existing {
+        for (to, sql) in MIGRATIONS.iter().filter(|(to, _)| *to > version) {
+            tx.execute_batch(sql)
+                .map_err(|e| anyhow::anyhow!("migração da sessão para a versão {} falhou: {}", to, e))?;
+        }
+    }
+    tx.execute_batch(SCHEMA)?;
+    tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+    tx.commit()?;
+    Ok(())
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+type Write = Box<dyn FnOnce(&mut Connection) -> anyhow::Result<()> + Send>;
+
+#[derive(Clone)]
+pub struct Session {
+    conn: Arc<Mutex<Connection>>,
+    writer: mpsc::Sender<Write>,
+}
+
+fn spawn_writer(conn: Arc<Mutex<Connection>>) -> anyhow::Result<mpsc::Sender<Write>> {
+    let (tx, rx) = mpsc::channel::<Write>();
+    thread::Builder::new()
+        .name("session-writer".into())
+        .spawn(move || {
+            for write in rx {
+                if let Err(e) = write(&mut conn.lock().unwrap()) {
+                    eprintln!("❌ [Session] Erro ao gravar na sessão: {e}");
+                }
+            }
+        })?;
+    Ok(tx)
+}
+
+impl Session {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut conn = Connection::open(path)
+            .map_err(|e| anyhow::anyhow!("não foi possível abrir a sessão {:?}: {}", path, e))?;
+
+        // WAL keeps what was committed when the process is killed mid-crawl
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        migrate(&mut conn)?;
+
+        let conn = Arc::new(Mutex::new(conn));
+        Ok(Self {
+            writer: spawn_writer(conn.clone())?,
+            conn,
+        })
+    }
+
+    // writes coming from the async workers go to the writer thread, sqlite calls block
+    pub fn write_later(&self, write: impl FnOnce(&mut Connection) -> anyhow::Result<()> + Send + 'static) {
+        if self.writer.send(Box::new(write)).is_err() {
+            eprintln!("❌ [Session] Thread de escrita encerrada, gravação descartada.");
+        }
+    }
+
+    // waits for every queued write, so nothing is lost when the crawl ends
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        self.write_later(move |_| {
+            let _ = done.send(());
+            Ok(())
+        });
+        let _ = wait.recv();
+    }
+
+    pub fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+
+    pub fn record_exchange(&self, resp: &InterceptedResponse) -> anyhow::Result<Option<i64>> {
+        let Some(exchange) = resp.exchange.as_deref() else {
+            return Ok(None);
+        };
+
+        let at = now();
+        let path = resp.path.split('?').next().unwrap_or_default();
+        let body = resp.decoded_body();
+        let body_hash = body.map(content_hash);
+
+        let mut conn = self.connection();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO hosts (name, scheme, first_seen, last_seen, exchanges) VALUES (?1, ?2, ?3, ?3, 1)
+             ON CONFLICT (name) DO UPDATE SET last_seen = ?3, exchanges = exchanges + 1",
+            params![resp.host, resp.scheme, at],
+        )?;
+
+        let endpoint_id: i64 = tx.query_row(
+            "INSERT INTO endpoints (host, method, path, last_status, hits, first_seen, last_seen)
+             VALUES (?1, ?2, ?3, ?4, 1, ?5, ?5)
+             ON CONFLICT (host, method, path)
+             DO UPDATE SET last_status = ?4, hits = hits + 1, last_seen = ?5
+             RETURNING id",
+            params![resp.host, exchange.method, path, exchange.status, at],
+            |row| row.get(0),
+        )?;
+
+        if let (Some(body), Some(hash)) = (body, &body_hash) {
+            tx.execute(
+                "INSERT OR IGNORE INTO bodies (hash, size, content_type, body) VALUES (?1, ?2, ?3, ?4)",
+                params![hash, body.len(), resp.content_type, body],
+            )?;
+        }
+
+        tx.execute(
+            "INSERT INTO exchanges (at, method, url, host, endpoint_id, status, content_type, content_encoding,
+                request_headers, request_body, response_headers, body_hash, conn, event_id, page)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                at,
+                exchange.method,
+                exchange.url,
+                resp.host,
+                endpoint_id,
+                exchange.status,
+                resp.content_type,
+                resp.content_encoding,
+                serde_json::to_string(&exchange.request_headers)?,
+                exchange.request_body,
+                serde_json::to_string(&exchange.response_headers)?,
+                body_hash,
+                serde_json::to_string(&resp.conn)?,
+                resp.provenance.id as i64,
+                resp.provenance.page,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+
+        tx.commit()?;
+        Ok(Some(id))
+    }
+
+    pub fn body(&self, hash: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self
+            .connection()
+            .query_row("SELECT body FROM bodies WHERE hash = ?1", [hash], |row| row.get(0))
+            .optional()?)
+    }
+
+    // `body` is only given the first time a hash is seen
+    pub fn store_body(&self, url: &str, hash: &str, content_type: &str, body: Option<Vec<u8>>) {
+        let (url, hash, content_type) = (url.to_string(), hash.to_string(), content_type.to_string());
+        self.write_later(move |conn| {
+            let tx = conn.transaction()?;
+            if let Some(body) = body {
+                tx.execute(
+                    "INSERT OR IGNORE INTO bodies (hash, size, content_type, body) VALUES (?1, ?2, ?3, ?4)",
+                    params![hash, body.len(), content_type, body],
+                )?;
+            }
+            tx.execute(
+                "INSERT OR IGNORE INTO body_urls (url, hash, at) VALUES (?1, ?2, ?3)",
+                params![url, hash, now()],
+            )?;
+            tx.commit()?;
+            Ok(())
+        });
+    }
+
+    pub fn mark_analyzed(&self, hash: &str) {
+        let hash = hash.to_string();
+        self.write_later(move |conn| {
+            conn.execute(
+                "INSERT OR IGNORE INTO analyzed_bodies (hash, at) VALUES (?1, ?2)",
+                params![hash, now()],
+            )?;
+            Ok(())
+        });
+    }
+
+    pub fn analyzed_bodies(&self) -> anyhow::Result<HashSet<String>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare("SELECT hash FROM analyzed_bodies")?;
+        let hashes = stmt.query_map([], |row| row.get(0))?.collect::<Result<_, _>>()?;
+        Ok(hashes)
+    }
+
+    pub fn body_urls(&self) -> anyhow::Result<HashSet<(String, String)>> {
+        let conn = self.connection();
+        let mut stmt = conn.prepare("SELECT url, hash FROM body_urls")?;
+        let urls = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok(urls)
+    }
+
+    pub fn enqueue_urls(&self, urls: &[String]) -> anyhow::Result<()> {
+        let mut conn = self.connection();
+        let tx = conn.transaction()?;
+        let at = now();
+        for url in urls {
+            tx.execute(
+                "INSERT OR IGNORE INTO crawl_urls (url, added_at) VALUES (?1, ?2)",
+                params![url, at],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // pages that failed to load stay pending, so they are retried on resume
+    pub fn pending_urls(&self, urls: &[String]) -> anyhow::Result<Vec<String>> {
+        let conn = self.connection();
+        let mut visited = conn.prepare("SELECT visited_at IS NOT NULL FROM crawl_urls WHERE url = ?1")?;
+
+        let mut pending = vec![];
+        for url in urls {
+            let done: Option<bool> = visited.query_row([url], |row| row.get(0)).optional()?;
+            if !done.unwrap_or(false) {
+                pending.push(url.clone());
+            }
+        }
+        Ok(pending)
+    }
+
+    pub fn mark_visited(&self, url: &str) -> anyhow::Result<()> {
+        self.connection().execute(
+            "UPDATE crawl_urls SET visited_at = ?2 WHERE url = ?1",
+            params![url, now()],
+        )?;
+        Ok(())
+    }
+}
+
+fn insert_finding(conn: &Connection, finding: &Finding) -> anyhow::Result<bool> {
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO findings (at, kind, value, source, host, stage, line, col, confidence, detail,
+            event_id, parent_id, trail)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        params![
+            now(),
+            finding.kind,
+            finding.value,
+            finding.source,
+            finding.host,
+            finding.stage,
+            finding.position.map(|p| p.line),
+            finding.position.map(|p| p.column),
+            format!("{:?}", finding.confidence).to_lowercase(),
+            finding.detail,
+            finding.event as i64,
+            finding.parent.map(|p| p as i64),
+            serde_json::to_string(&finding.trail)?,
+        ],
+    )?;
+    Ok(inserted > 0)
+}
+
+impl FindingSink for Session {
+    fn report(&self, finding: &Finding) -> anyhow::Result<()> {
+        let finding = finding.clone();
+        self.write_later(move |conn| insert_finding(conn, &finding).map(|_| ()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::HeaderMap;
+
+    use super::*;
+    use crate::analyzer::{intercepted::ConnectionInfo, stage::StageId};
+
+    fn temp_db(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("mboitata-session-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join(SESSION_FILE)
+    }
+
+    fn response() -> InterceptedResponse {
+        InterceptedResponse::new(
+            "/app.js".into(),
+            "https".into(),
+            "a.test".into(),
+            &HeaderMap::new(),
+            b"body".to_vec(),
+            ConnectionInfo::default(),
+        )
+    }
+
+    fn count(session: &Session, table: &str) -> i64 {
+        session
+            .connection()
+            .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn databases_without_provenance_columns_are_migrated() {
+        let path = temp_db("migrate");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE exchanges (id INTEGER PRIMARY KEY, at INTEGER NOT NULL, method TEXT NOT NULL,
+                    url TEXT NOT NULL, host TEXT NOT NULL, endpoint_id INTEGER, status INTEGER NOT NULL,
+                    content_type TEXT NOT NULL, content_encoding TEXT NOT NULL, request_headers TEXT NOT NULL,
+                    request_body BLOB NOT NULL, response_headers TEXT NOT NULL, body_hash TEXT, conn TEXT NOT NULL);
+                 CREATE TABLE findings (id INTEGER PRIMARY KEY, at INTEGER NOT NULL, kind TEXT NOT NULL,
+                    value TEXT NOT NULL, source TEXT NOT NULL, host TEXT NOT NULL, stage TEXT NOT NULL,
+                    line INTEGER, col INTEGER, confidence TEXT NOT NULL, detail TEXT);
+                 INSERT INTO findings (at, kind, value, source, host, stage, confidence)
+                    VALUES (0, 'url', 'https://old.test/', 'https://a.test/', 'a.test', 'scan', 'medium');",
+            )
+            .unwrap();
+
+        let session = Session::open(&path).unwrap();
+        let resp = response();
+        session.report(&Finding::new(StageId::Scan, "url", "https://b.test/", &resp)).unwrap();
+        session.flush();
+
+        assert_eq!(count(&session, "findings"), 2);
+        let trail: String = session
+            .connection()
+            .query_row("SELECT trail FROM findings WHERE value = 'https://old.test/'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(trail, "[]");
+
+        // opening it again does not run the migration twice
+        drop(session);
+        Session::open(&path).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn findings_are_written_by_the_writer_thread() {
+        let path = temp_db("findings");
+        let session = Session::open(&path).unwrap();
+        let resp = response();
+
+        let finding = Finding::new(StageId::Scan, "url", "https://b.test/", &resp);
+        session.report(&finding).unwrap();
+        session.report(&finding).unwrap();
+        session.flush();
+
+        assert_eq!(count(&session, "findings"), 1);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,101 @@
+use std::path::Path;
+
+use anyhow::bail;
+use rusqlite::{params_from_iter, types::ValueRef};
+
+use crate::{config::SessionCommand, session::Session};
+
+pub fn run(command: &SessionCommand, db: &Path) -> anyhow::Result<()> {
+    if !db.exists() {
+        bail!("sessão {:?} não encontrada", db);
+    }
+    let session = Session::open(db)?;
+
+    match command {
+        SessionCommand::Summary => {
+            for table in ["hosts", "endpoints", "exchanges", "bodies", "findings", "crawl_urls"] {
+                print_query(&session, &format!("SELECT '{table}', COUNT(*) FROM {table}"), &[], false)?;
+            }
+            print_query(
+                &session,
+                "SELECT 'crawl_urls pendentes', COUNT(*) FROM crawl_urls WHERE visited_at IS NULL",
+                &[],
+                false,
+            )
+        }
+        SessionCommand::Hosts => print_query(
+            &session,
+            "SELECT name, scheme, exchanges, datetime(first_seen, 'unixepoch'), datetime(last_seen, 'unixepoch')
+             FROM hosts ORDER BY exchanges DESC",
+            &[],
+            false,
+        ),
+        SessionCommand::Endpoints { host } => print_query(
+            &session,
+            "SELECT host, method, path, last_status, hits FROM endpoints
+             WHERE ?1 IS NULL OR host = ?1 ORDER BY host, path, method",
+            std::slice::from_ref(host),
+            false,
+        ),
+        SessionCommand::Findings {
+            host,
+            kind,
+            min_confidence,
+        } => {
+            let confidences = ["low", "medium", "high"]
+                .iter()
+                .skip(*min_confidence as usize)
+                .map(|c| format!("'{c}'"))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            print_query(
+                &session,
+                &format!(
+                    "SELECT kind, value, source || IFNULL(':' || line || ':' || col, ''), confidence, stage,
+                        (SELECT group_concat(value, ' → ') FROM json_each(trail))
+                     FROM findings
+                     WHERE (?1 IS NULL OR host = ?1) AND (?2 IS NULL OR kind = ?2) AND confidence IN ({confidences})
+                     ORDER BY host, kind, value"
+                ),
+                &[host.clone(), kind.clone()],
+                false,
+            )
+        }
+        SessionCommand::Query { sql } => {
+            if !session.connection().prepare(sql)?.readonly() {
+                bail!("apenas consultas de leitura são permitidas");
+            }
+            print_query(&session, sql, &[], true)
+        }
+    }
+}
+
+fn print_query(session: &Session, sql: &str, params: &[Option<String>], header: bool) -> anyhow::Result<()> {
+    let conn = session.connection();
+    let mut stmt = conn.prepare(sql)?;
+    let columns = stmt.column_count();
+
+    if header {
+        println!("{}", stmt.column_names().join("\t"));
+    }
+
+    let mut rows = stmt.query(params_from_iter(params))?;
+    while let Some(row) = rows.next()? {
+        let values = (0..columns)
+            .map(|i| row.get_ref(i).map(display))
+            .collect::<Result<Vec<_>, _>>()?;
+        println!("{}", values.join("\t"));
+    }
+    Ok(())
+}
+
+fn display(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => "-".into(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) => f.to_string(),
+        ValueRef::Text(t) => String::from_utf8_lossy(t).replace(['\t', '\n'], " "),
+        ValueRef::Blob(b) => format!("<{} bytes>", b.len()),
+    }
+}